@@ -7,9 +7,17 @@ fn main() {
 
     let client = Client::open_in_memory();
     client.execute_batch(include_str!("src/migration/000_initial.sql"));
+    client.execute_batch(include_str!("src/migration/001_failure_kind.sql"));
+    client.execute_batch(include_str!("src/migration/002_failure_details.sql"));
+    client.execute_batch(include_str!("src/migration/003_full_hash.sql"));
+    client.execute_batch(include_str!("src/migration/004_problem_version.sql"));
+    client.execute_batch(include_str!("src/migration/005_session.sql"));
+    client.execute_batch(include_str!("src/migration/006_user_provider.sql"));
+    client.execute_batch(include_str!("src/migration/007_api_token.sql"));
+    client.execute_batch(include_str!("src/migration/008_instance_hash.sql"));
     let code = generate(client);
     fs::write(dest_path, code).unwrap();
 
-    println!("cargo::rerun-if-changed=src/migration/000_initial.sql");
+    println!("cargo::rerun-if-changed=src/migration");
     println!("cargo::rerun-if-changed=build.rs");
 }
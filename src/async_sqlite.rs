@@ -1,6 +1,6 @@
 use std::{
     ops::Deref,
-    sync::{Arc, Condvar, Mutex, MutexGuard},
+    sync::{Arc, Condvar, Mutex, MutexGuard, PoisonError},
     thread,
 };
 
@@ -37,8 +37,7 @@ impl SharedConnection {
         thread::spawn(move || {
             while let Some(func) = receiver.blocking_recv() {
                 func(&mut inner.lock());
-                *inner.updates.lock().unwrap() = true;
-                inner.cvar.notify_all();
+                inner.notify();
             }
         });
         res
@@ -80,8 +79,16 @@ impl Inner {
     //     func(&mut conn)
     // }
 
+    // a panic while the lock is held does not leave the connection in a bad state,
+    // sqlite rolls back unfinished statements, so the connection can still be used.
     pub fn lock(&self) -> MutexGuard<'_, Connection> {
-        self.conn.lock().unwrap()
+        self.conn.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    // wake up everyone that is waiting for changes
+    pub fn notify(&self) {
+        *self.updates.lock().unwrap() = true;
+        self.cvar.notify_all();
     }
 
     pub fn wait(&self) {
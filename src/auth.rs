@@ -0,0 +1,372 @@
+use std::{collections::HashMap, fs, future::Future, path::Path, pin::Pin};
+
+use anyhow::Context;
+use argon2::{password_hash::PasswordHashString, Argon2, PasswordVerifier};
+use oauth2::{
+    basic::BasicClient, reqwest::async_http_client, AuthUrl, AuthorizationCode, ClientId,
+    ClientSecret, CsrfToken, RedirectUrl, Scope, TokenResponse, TokenUrl,
+};
+use serde::Deserialize;
+use tokio::sync::OnceCell;
+
+pub const AUTH_CONFIG: &str = "config/auth.toml";
+// from before there were multiple providers, it only contains the github client
+const LEGACY_OAUTH_CONFIG: &str = "config/oauth.toml";
+
+pub type BoxFuture<'a, T> = Pin<Box<dyn Future<Output = T> + Send + 'a>>;
+
+/// A user as it is known by an auth provider.
+pub struct AuthUser {
+    /// the id of the user at the provider, this never changes
+    pub subject: String,
+    /// the name that is shown on the site
+    pub login: String,
+}
+
+pub enum LoginStart {
+    /// Send the user to the provider, which redirects back to the callback with `state`.
+    Redirect { url: String, state: String },
+    /// Ask for a username and password, the form is posted to the callback.
+    Form,
+}
+
+/// A way for users to log in.
+///
+/// Every provider has a callback at `/login/<name>/callback`, it receives the query parameters of the
+/// redirect or the fields of the login form.
+pub trait AuthProvider: Send + Sync {
+    /// Used in urls and stored in `user.provider`, so it should not change.
+    fn name(&self) -> &str;
+
+    fn start(&self) -> BoxFuture<'_, anyhow::Result<LoginStart>>;
+
+    fn finish<'a>(
+        &'a self,
+        params: &'a HashMap<String, String>,
+    ) -> BoxFuture<'a, anyhow::Result<AuthUser>>;
+}
+
+#[derive(Deserialize)]
+struct AuthConfig {
+    github: Option<ClientConfig>,
+    #[serde(default)]
+    oidc: HashMap<String, OidcConfig>, // the key is used as the provider name
+    local: Option<LocalConfig>,
+}
+
+#[derive(Deserialize)]
+struct ClientConfig {
+    client_id: String,
+    client_secret: String,
+}
+
+#[derive(Deserialize)]
+struct LegacyConfig {
+    github_client_id: String,
+    github_client_secret: String,
+}
+
+#[derive(Deserialize)]
+struct OidcConfig {
+    issuer: String,
+    #[serde(flatten)]
+    client: ClientConfig,
+}
+
+#[derive(Deserialize)]
+struct LocalConfig {
+    // username to argon2 hash of the password, meant for self hosted servers.
+    // hashes look like `$argon2id$v=19$...`, for example from `echo -n <password> | argon2 <salt> -id -e`
+    #[serde(default)]
+    users: HashMap<String, String>,
+    // anyone can log in with a username that is not in `users`, only use this for offline testing.
+    // the login form has no csrf protection, so with this any site can log a visitor in as anyone
+    #[serde(default)]
+    dev: bool,
+}
+
+/// All configured auth providers.
+pub struct Providers(Vec<Box<dyn AuthProvider>>);
+
+impl Providers {
+    /// The callback urls are relative to `base_url`.
+    pub fn load(path: &Path, base_url: &str) -> anyhow::Result<Self> {
+        let config = if path.exists() {
+            toml::from_str(&fs::read_to_string(path)?)
+                .with_context(|| format!("could not parse {}", path.display()))?
+        } else if Path::new(LEGACY_OAUTH_CONFIG).exists() {
+            let legacy: LegacyConfig = toml::from_str(&fs::read_to_string(LEGACY_OAUTH_CONFIG)?)
+                .with_context(|| format!("could not parse {LEGACY_OAUTH_CONFIG}"))?;
+            AuthConfig {
+                github: Some(ClientConfig {
+                    client_id: legacy.github_client_id,
+                    client_secret: legacy.github_client_secret,
+                }),
+                oidc: HashMap::new(),
+                local: None,
+            }
+        } else {
+            println!("no {}, nobody can log in", path.display());
+            return Ok(Self(vec![]));
+        };
+
+        let callback = |name: &str| format!("{base_url}/login/{name}/callback");
+        let mut providers: Vec<Box<dyn AuthProvider>> = vec![];
+        if let Some(github) = config.github {
+            let client = BasicClient::new(
+                ClientId::new(github.client_id),
+                Some(ClientSecret::new(github.client_secret)),
+                AuthUrl::new("https://github.com/login/oauth/authorize".to_owned())?,
+                Some(TokenUrl::new(
+                    "https://github.com/login/oauth/access_token".to_owned(),
+                )?),
+            )
+            .set_redirect_uri(RedirectUrl::new(callback("github"))?);
+            providers.push(Box::new(Github { client }));
+        }
+        for (name, oidc) in config.oidc {
+            providers.push(Box::new(Oidc {
+                redirect_url: callback(&name),
+                name,
+                issuer: oidc.issuer,
+                client: oidc.client,
+                metadata: OnceCell::new(),
+            }));
+        }
+        if let Some(local) = config.local {
+            let mut users = HashMap::new();
+            for (username, hash) in local.users {
+                let hash = PasswordHashString::new(&hash)
+                    .map_err(|e| anyhow::anyhow!("invalid password hash for `{username}`: {e}"))?;
+                users.insert(username, hash);
+            }
+            providers.push(Box::new(Local {
+                users,
+                dev: local.dev,
+            }));
+        }
+        Ok(Self(providers))
+    }
+
+    pub fn get(&self, name: &str) -> Option<&dyn AuthProvider> {
+        self.0.iter().find(|p| p.name() == name).map(|p| &**p)
+    }
+
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.0.iter().map(|p| p.name())
+    }
+}
+
+fn http_client() -> reqwest::Client {
+    reqwest::Client::builder()
+        .user_agent("wasm-bench")
+        .build()
+        .unwrap()
+}
+
+// log in with a github oauth app
+struct Github {
+    client: BasicClient,
+}
+
+impl AuthProvider for Github {
+    fn name(&self) -> &str {
+        "github"
+    }
+
+    fn start(&self) -> BoxFuture<'_, anyhow::Result<LoginStart>> {
+        let (url, state) = self.client.authorize_url(CsrfToken::new_random).url();
+        Box::pin(async move {
+            Ok(LoginStart::Redirect {
+                url: url.to_string(),
+                state: state.secret().to_owned(),
+            })
+        })
+    }
+
+    fn finish<'a>(
+        &'a self,
+        params: &'a HashMap<String, String>,
+    ) -> BoxFuture<'a, anyhow::Result<AuthUser>> {
+        Box::pin(async move {
+            let code = params.get("code").context("missing code")?;
+            let token = self
+                .client
+                .exchange_code(AuthorizationCode::new(code.clone()))
+                .request_async(async_http_client)
+                .await?;
+
+            let text = http_client()
+                .get("https://api.github.com/user")
+                .bearer_auth(token.access_token().secret())
+                .header("X-GitHub-Api-Version", "2022-11-28")
+                .header("Accept", "application/vnd.github+json")
+                .send()
+                .await?
+                .error_for_status()?
+                .text()
+                .await?;
+
+            let val: serde_json::Value = serde_json::from_str(&text)?;
+            let id = val.get("id").and_then(|id| id.as_i64());
+            let login = val.get("login").and_then(|login| login.as_str());
+            match (id, login) {
+                (Some(id), Some(login)) => Ok(AuthUser {
+                    subject: id.to_string(),
+                    login: login.to_owned(),
+                }),
+                _ => anyhow::bail!("unexpected github user response: {text}"),
+            }
+        })
+    }
+}
+
+// log in with any OpenID Connect provider, the endpoints are found with discovery
+struct Oidc {
+    name: String,
+    issuer: String,
+    client: ClientConfig,
+    redirect_url: String,
+    metadata: OnceCell<OidcMetadata>,
+}
+
+#[derive(Deserialize)]
+struct OidcMetadata {
+    authorization_endpoint: String,
+    token_endpoint: String,
+    userinfo_endpoint: String,
+}
+
+impl Oidc {
+    // the metadata is only fetched once, on the first login
+    async fn metadata(&self) -> anyhow::Result<&OidcMetadata> {
+        self.metadata
+            .get_or_try_init(|| async {
+                let url = format!(
+                    "{}/.well-known/openid-configuration",
+                    self.issuer.trim_end_matches('/')
+                );
+                let text = http_client()
+                    .get(url)
+                    .send()
+                    .await?
+                    .error_for_status()?
+                    .text()
+                    .await?;
+                Ok(serde_json::from_str(&text)?)
+            })
+            .await
+    }
+
+    async fn client(&self) -> anyhow::Result<BasicClient> {
+        let metadata = self.metadata().await?;
+        let client = BasicClient::new(
+            ClientId::new(self.client.client_id.clone()),
+            Some(ClientSecret::new(self.client.client_secret.clone())),
+            AuthUrl::new(metadata.authorization_endpoint.clone())?,
+            Some(TokenUrl::new(metadata.token_endpoint.clone())?),
+        )
+        .set_redirect_uri(RedirectUrl::new(self.redirect_url.clone())?);
+        Ok(client)
+    }
+}
+
+impl AuthProvider for Oidc {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn start(&self) -> BoxFuture<'_, anyhow::Result<LoginStart>> {
+        Box::pin(async move {
+            let (url, state) = self
+                .client()
+                .await?
+                .authorize_url(CsrfToken::new_random)
+                .add_scope(Scope::new("openid".to_owned()))
+                .add_scope(Scope::new("profile".to_owned()))
+                .url();
+            Ok(LoginStart::Redirect {
+                url: url.to_string(),
+                state: state.secret().to_owned(),
+            })
+        })
+    }
+
+    fn finish<'a>(
+        &'a self,
+        params: &'a HashMap<String, String>,
+    ) -> BoxFuture<'a, anyhow::Result<AuthUser>> {
+        Box::pin(async move {
+            let code = params.get("code").context("missing code")?;
+            let token = self
+                .client()
+                .await?
+                .exchange_code(AuthorizationCode::new(code.clone()))
+                .request_async(async_http_client)
+                .await?;
+
+            // the userinfo endpoint is used instead of the id token, so no signature checks are needed
+            let text = http_client()
+                .get(&self.metadata().await?.userinfo_endpoint)
+                .bearer_auth(token.access_token().secret())
+                .send()
+                .await?
+                .error_for_status()?
+                .text()
+                .await?;
+
+            let val: serde_json::Value = serde_json::from_str(&text)?;
+            let field = |name: &str| val.get(name).and_then(|v| v.as_str());
+            let subject = field("sub").context("userinfo has no `sub`")?;
+            let login = field("preferred_username")
+                .or(field("name"))
+                .unwrap_or(subject);
+            Ok(AuthUser {
+                subject: subject.to_owned(),
+                login: login.to_owned(),
+            })
+        })
+    }
+}
+
+// log in with a username and password from the config, this works without internet
+struct Local {
+    users: HashMap<String, PasswordHashString>,
+    dev: bool,
+}
+
+impl AuthProvider for Local {
+    fn name(&self) -> &str {
+        "local"
+    }
+
+    fn start(&self) -> BoxFuture<'_, anyhow::Result<LoginStart>> {
+        Box::pin(async { Ok(LoginStart::Form) })
+    }
+
+    fn finish<'a>(
+        &'a self,
+        params: &'a HashMap<String, String>,
+    ) -> BoxFuture<'a, anyhow::Result<AuthUser>> {
+        Box::pin(async move {
+            let username = params.get("username").context("missing username")?.trim();
+            let password = params.get("password").map_or("", String::as_str);
+            if username.is_empty() {
+                anyhow::bail!("the username can not be empty");
+            }
+            let valid = match self.users.get(username) {
+                Some(hash) => Argon2::default()
+                    .verify_password(password.as_bytes(), &hash.password_hash())
+                    .is_ok(),
+                None => self.dev,
+            };
+            if !valid {
+                anyhow::bail!("wrong username or password");
+            }
+            Ok(AuthUser {
+                subject: username.to_owned(),
+                login: username.to_owned(),
+            })
+        })
+    }
+}
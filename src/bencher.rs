@@ -1,105 +1,688 @@
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::fmt::Display;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::{Condvar, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use rand::{thread_rng, RngCore};
+use rusqlite::Connection;
 use rust_query::client::QueryBuilder;
 use rust_query::value::{UnixEpoch, Value};
-use wasmtime::{Config, Engine};
+use serde::Serialize;
 
 use crate::tables::{ExecutionDummy, FailureDummy, Instance};
 use crate::{
+    async_sqlite::SharedConnection,
+    cache::{EngineKind, ModuleCache},
+    config::ServerConfig,
+    db,
     hash::FileHash,
-    solution::Solution,
+    problem::{Problem, ProblemDir, TaskInstance},
+    solution::{Fault, Solution, EPOCH_TICK},
     tables::{self},
     AppState,
 };
 
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
 struct QueuedTask {
     solution_hash: FileHash,
     problem_hash: FileHash,
-    instance_seed: i64,
+    test: Test,
 }
 
-pub fn bencher_main(app: AppState) -> anyhow::Result<()> {
-    let problem_engine = Engine::default();
-    let solution_engine = Engine::new(Config::new().consume_fuel(true))?;
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+enum Test {
+    // one of the leaderboard instances
+    Instance(i64),
+    // all remaining random tests, these run after the leaderboard instances passed
+    Random,
+}
+
+// lower priorities are scheduled first
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum Priority {
+    NewSubmission,
+    RandomTests,
+    // old solutions that need to run on a new instance
+    Backfill,
+}
+
+struct Candidate {
+    task: QueuedTask,
+    priority: Priority,
+    // timestamp of the solution
+    submitted: i64,
+    // estimated fuel, so that a broken solution fails on a cheap instance
+    cost: i64,
+}
+
+impl QueuedTask {
+    fn solution(&self) -> (FileHash, FileHash) {
+        (self.solution_hash, self.problem_hash)
+    }
+}
+
+impl Display for QueuedTask {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "solution {} on problem {}",
+            self.solution_hash, self.problem_hash
+        )?;
+        match self.test {
+            Test::Instance(seed) => write!(f, " with seed {}", seed as u64),
+            Test::Random => f.write_str(" with random tests"),
+        }
+    }
+}
+
+/// Health of the bencher, this is shown on the status page.
+#[derive(Default, Clone, Serialize)]
+pub struct BencherStatus {
+    pub running: bool,
+    pub last_task: Option<String>,
+    pub queue_length: usize,
+    pub last_error: Option<String>,
+    pub crashes: u32,
+    // (solution, problem) of every pending task in the order they will run
+    #[serde(skip)]
+    pub queued: Vec<(FileHash, FileHash)>,
+}
+
+impl BencherStatus {
+    fn set_queue(&mut self, queued: Vec<(FileHash, FileHash)>) {
+        self.queue_length = queued.len();
+        self.queued = queued;
+    }
+
+    /// Number of tasks before the first task of this solution, if it has any queued.
+    pub fn queue_position(&self, solution: FileHash, problem: FileHash) -> Option<usize> {
+        self.queued.iter().position(|x| *x == (solution, problem))
+    }
+}
+
+// what gets stored in the `failure` table
+struct FailureInfo {
+    kind: &'static str,
+    message: Option<String>,
+    expected: Option<i64>,
+    answer: Option<i64>,
+}
+
+impl FailureInfo {
+    fn new(kind: &'static str, message: Option<String>) -> Self {
+        FailureInfo {
+            kind,
+            message,
+            expected: None,
+            answer: None,
+        }
+    }
+}
+
+struct Engines {
+    problem: ModuleCache,
+    solution: ModuleCache,
+}
+
+/// Tasks that are waiting for a worker, shared between all bench workers.
+#[derive(Default)]
+struct TaskQueue {
+    state: Mutex<QueueState>,
+    cvar: Condvar,
+}
+
+#[derive(Default)]
+struct QueueState {
+    pending: VecDeque<QueuedTask>,
+    // tasks that a worker is busy with, these should not be queued again
+    running: HashSet<QueuedTask>,
+    // tasks that finished since the last refresh started, the query might not have seen them
+    finished: HashSet<QueuedTask>,
+    // (solution, problem) that failed since the last refresh started, the query might not have seen them
+    failed: HashSet<(FileHash, FileHash)>,
+    closed: bool,
+}
+
+impl TaskQueue {
+    // call this before querying the database for the tasks that are passed to `replace`
+    // older results are in the database, so the query already leaves those tasks out.
+    fn start_refresh(&self) {
+        let mut state = self.state.lock().unwrap();
+        state.finished.clear();
+        state.failed.clear();
+    }
+
+    fn replace(&self, tasks: Vec<QueuedTask>) {
+        let mut state = self.state.lock().unwrap();
+        let state = &mut *state;
+        state.pending = tasks
+            .into_iter()
+            .filter(|task| !state.running.contains(task))
+            .filter(|task| !state.finished.contains(task))
+            .filter(|task| !state.failed.contains(&task.solution()))
+            .collect();
+        self.cvar.notify_all();
+    }
+
+    // remove the remaining tasks of a solution that failed.
+    // the failure should be in the database before this is called
+    fn fail(&self, task: &QueuedTask) {
+        let mut state = self.state.lock().unwrap();
+        state.pending.retain(|t| t.solution() != task.solution());
+        state.failed.insert(task.solution());
+    }
+
+    // returns `None` when the queue is closed
+    fn take(&self) -> Option<QueuedTask> {
+        let state = self.state.lock().unwrap();
+        let mut state = self
+            .cvar
+            .wait_while(state, |s| s.pending.is_empty() && !s.closed)
+            .unwrap();
+        if state.closed {
+            return None;
+        }
+        let task = state.pending.pop_front().unwrap();
+        state.running.insert(task);
+        Some(task)
+    }
+
+    fn pending(&self) -> Vec<(FileHash, FileHash)> {
+        let state = self.state.lock().unwrap();
+        let pending = state.pending.iter();
+        pending.map(QueuedTask::solution).collect()
+    }
+
+    // the result of the task should be in the database before this is called.
+    // returns `true` when this was the last queued task of the solution.
+    fn finish(&self, task: &QueuedTask) -> bool {
+        let mut state = self.state.lock().unwrap();
+        state.running.remove(task);
+        state.finished.insert(*task);
+        let mut tasks = state.pending.iter().chain(&state.running);
+        !tasks.any(|t| t.solution() == task.solution())
+    }
+
+    fn is_closed(&self) -> bool {
+        self.state.lock().unwrap().closed
+    }
+
+    fn close(&self) {
+        self.state.lock().unwrap().closed = true;
+        self.cvar.notify_all();
+    }
+}
+
+/// Number of worker threads, can be set with `bench_workers` in the server config.
+fn num_workers(config: &ServerConfig) -> usize {
+    if let Some(num) = config.bench_workers {
+        return num;
+    }
+    thread::available_parallelism().map_or(1, |x| x.get())
+}
+
+const MIN_BACKOFF: Duration = Duration::from_secs(1);
+const MAX_BACKOFF: Duration = Duration::from_secs(300);
+
+/// Runs the bencher and restarts it with exponential backoff when it crashes.
+pub fn supervise_bencher(app: AppState) {
+    let mut backoff = MIN_BACKOFF;
     loop {
-        // wait for database state to change
-        app.conn.wait();
-        println!("querying the database for queue");
-        let conn = app.conn.lock();
+        app.bencher.lock().unwrap().running = true;
+        let started = Instant::now();
+        let res = panic::catch_unwind(AssertUnwindSafe(|| bencher_main(app.clone())));
+        let error = match res {
+            Ok(Ok(())) => "bencher stopped".to_owned(),
+            Ok(Err(err)) => format!("{err:#}"),
+            Err(payload) => match payload.downcast::<String>() {
+                Ok(msg) => format!("panic: {msg}"),
+                Err(payload) => match payload.downcast::<&str>() {
+                    Ok(msg) => format!("panic: {msg}"),
+                    Err(_) => "panic".to_owned(),
+                },
+            },
+        };
+        println!("bencher crashed: {error}");
 
-        let queue = conn.new_query(|q| {
-            let instance = q.table(tables::Instance);
-            let solution = q.table(tables::Solution);
-            q.filter((&instance.problem).eq(&solution.problem));
-
-            let is_executed = q.query(|q| {
-                let exec = q.table(tables::Execution);
-                q.filter_on(&exec.instance, &instance);
-                q.filter_on(&exec.solution, &solution);
-                q.group().exists()
-            });
-            // not executed yet
-            q.filter(is_executed.not());
-
-            let fail = q.query(|q| {
-                let failure = q.table(tables::Failure);
-                q.filter_on(&failure.solution, &solution);
-                q.group().exists()
-            });
-            // has not failed
-            q.filter(fail.not());
-
-            q.into_vec(u32::MAX, |row| QueuedTask {
+        let mut status = app.bencher.lock().unwrap();
+        status.running = false;
+        status.last_error = Some(error);
+        status.crashes += 1;
+        drop(status);
+
+        // a bencher that ran for a while is considered healthy again
+        if started.elapsed() > MAX_BACKOFF {
+            backoff = MIN_BACKOFF;
+        }
+        thread::sleep(backoff);
+        backoff = (backoff * 2).min(MAX_BACKOFF);
+    }
+}
+
+pub fn bencher_main(app: AppState) -> anyhow::Result<()> {
+    let engines = Engines {
+        problem: ModuleCache::new(EngineKind::Problem, app.store.clone())?,
+        solution: ModuleCache::new(EngineKind::Solution, app.store.clone())?,
+    };
+    let queue = TaskQueue::default();
+
+    thread::scope(|s| {
+        let workers: Vec<_> = (0..num_workers(&app.config).max(1))
+            .map(|_| s.spawn(|| bench_worker(&app, &queue, &engines)))
+            .collect();
+
+        // this drives the wall-clock limit of solutions
+        s.spawn(|| {
+            while !queue.is_closed() {
+                thread::sleep(EPOCH_TICK);
+                engines.solution.engine().increment_epoch();
+            }
+        });
+
+        // workers only stop when they encounter an error, this closes the queue.
+        // the queue is also closed when this loop panics, so the other threads stop too
+        let close = CloseOnExit {
+            queue: &queue,
+            conn: &app.conn,
+        };
+        while !queue.is_closed() {
+            println!("querying the database for queue");
+            queue.start_refresh();
+            let tasks = get_queue(&app.conn.lock(), &app.problem_dir.get());
+            println!("{} new tasks queued", tasks.len());
+            queue.replace(tasks);
+            app.bencher.lock().unwrap().set_queue(queue.pending());
+            // wait for database state to change
+            app.conn.wait();
+        }
+
+        drop(close);
+        workers
+            .into_iter()
+            .map(|w| w.join().unwrap())
+            .collect::<anyhow::Result<()>>()
+    })
+}
+
+fn get_queue(conn: &Connection, problem_dir: &ProblemDir) -> Vec<QueuedTask> {
+    let mut candidates = get_instance_tasks(conn);
+    candidates.extend(get_random_tasks(conn, problem_dir));
+    // problems can be removed or replaced by a new version while the server is running
+    candidates.retain(|c| problem_dir.is_current(c.task.problem_hash));
+    schedule(candidates, &get_submitters(conn))
+}
+
+// new submissions go first, then users with fewer queued tasks, then the oldest solutions.
+// the instances of a solution are ordered from cheap to expensive.
+fn schedule(
+    mut candidates: Vec<Candidate>,
+    submitters: &HashMap<FileHash, Vec<String>>,
+) -> Vec<QueuedTask> {
+    let users = |c: &Candidate| submitters.get(&c.task.solution_hash).into_iter().flatten();
+
+    let mut load: HashMap<&str, usize> = HashMap::new();
+    for candidate in &candidates {
+        for user in users(candidate) {
+            *load.entry(user).or_default() += 1;
+        }
+    }
+
+    candidates.sort_by_cached_key(|c| {
+        let user_load = users(c).map(|user| load[user.as_str()]).min();
+        let solution = i64::from(c.task.solution_hash);
+        let user_load = user_load.unwrap_or_default();
+        (c.priority, user_load, c.submitted, solution, c.cost)
+    });
+    candidates.into_iter().map(|c| c.task).collect()
+}
+
+// the users that submitted each solution
+fn get_submitters(conn: &Connection) -> HashMap<FileHash, Vec<String>> {
+    let submissions = conn.new_query(|q| {
+        let submission = q.table(tables::Submission);
+        q.into_vec(u32::MAX, |row| {
+            let solution_hash = FileHash::from(row.get(submission.solution.file_hash));
+            (solution_hash, row.get(submission.user.login))
+        })
+    });
+
+    let mut submitters: HashMap<_, Vec<_>> = HashMap::new();
+    for (solution_hash, user) in submissions {
+        submitters.entry(solution_hash).or_default().push(user);
+    }
+    submitters
+}
+
+fn get_instance_tasks(conn: &Connection) -> Vec<Candidate> {
+    conn.new_query(|q| {
+        let instance = q.table(tables::Instance);
+        q.filter(instance.invalid.eq(0));
+        let solution = q.table(tables::Solution);
+        q.filter((&instance.problem).eq(&solution.problem));
+
+        let is_executed = q.query(|q| {
+            let exec = q.table(tables::Execution);
+            q.filter_on(&exec.instance, &instance);
+            q.filter_on(&exec.solution, &solution);
+            q.group().exists()
+        });
+        // not executed yet
+        q.filter(is_executed.not());
+
+        let fail = q.query(|q| {
+            let failure = q.table(tables::Failure);
+            q.filter_on(&failure.solution, &solution);
+            q.group().exists()
+        });
+        // has not failed
+        q.filter(fail.not());
+
+        let max_fuel = q.query(|q| {
+            let exec = q.table(tables::Execution);
+            q.filter_on(&exec.instance, &instance);
+            q.group().max(exec.fuel_used)
+        });
+
+        q.into_vec(u32::MAX, |row| {
+            let task = QueuedTask {
                 solution_hash: row.get(solution.program.file_hash).into(),
                 problem_hash: row.get(instance.problem.file_hash).into(),
-                instance_seed: row.get(instance.seed),
-            })
+                test: Test::Instance(row.get(instance.seed)),
+            };
+            let submitted = row.get(solution.timestamp);
+            Candidate {
+                task,
+                priority: if row.get(instance.timestamp) > submitted {
+                    Priority::Backfill
+                } else {
+                    Priority::NewSubmission
+                },
+                submitted,
+                // instances that no solution ran on yet might be expensive
+                cost: row.get(max_fuel).unwrap_or(i64::MAX),
+            }
+        })
+    })
+}
+
+// solutions that passed all leaderboard instances, but not all random tests
+fn get_random_tasks(conn: &Connection, problem_dir: &ProblemDir) -> Vec<Candidate> {
+    let solutions = conn.new_query(|q| {
+        let solution = q.table(tables::Solution);
+
+        let fail = q.query(|q| {
+            let failure = q.table(tables::Failure);
+            q.filter_on(&failure.solution, &solution);
+            q.group().exists()
         });
+        // has not failed
+        q.filter(fail.not());
 
-        drop(conn);
+        let total_instances = q.query(|q| {
+            let instance = q.table(tables::Instance);
+            q.filter_on(&instance.problem, &solution.problem);
+            q.filter(instance.invalid.eq(0));
+            q.group().count_distinct(instance)
+        });
+        let executed = q.query(|q| {
+            let exec = q.table(tables::Execution);
+            q.filter_on(&exec.solution, &solution);
+            q.filter(exec.instance.invalid.eq(0));
+            q.group().count_distinct(exec)
+        });
 
-        println!("{} new tasks queued", queue.len());
+        q.into_vec(u32::MAX, |row| {
+            let task = QueuedTask {
+                solution_hash: row.get(solution.program.file_hash).into(),
+                problem_hash: row.get(solution.problem.file_hash).into(),
+                test: Test::Random,
+            };
+            let candidate = Candidate {
+                task,
+                priority: Priority::RandomTests,
+                submitted: row.get(solution.timestamp),
+                cost: 0,
+            };
+            let benched = row.get(executed) == row.get(total_instances);
+            (candidate, benched, row.get(solution.random_tests))
+        })
+    });
 
-        for task in queue {
-            let solution = Solution {
-                hash: task.solution_hash,
+    solutions
+        .into_iter()
+        .filter(|(candidate, benched, random_tests)| {
+            let Some(problem) = problem_dir.problems.get(&candidate.task.problem_hash) else {
+                return false;
             };
-            let problem = &app.problem_dir.problems[&task.problem_hash];
-            let instance = problem.generate(&problem_engine, task.instance_seed)?;
-
-            let run_result = solution.run(&solution_engine, &instance.input, problem.fuel_limit);
-
-            let conn = app.conn.lock();
-
-            if run_result.answer == Some(instance.answer) {
-                conn.new_query(|q| {
-                    let instance = q.table(Instance);
-                    q.filter(instance.problem.file_hash.eq(i64::from(task.problem_hash)));
-                    q.filter(instance.seed.eq(task.instance_seed));
-
-                    let solution = q.table(tables::Solution);
-                    q.filter(solution.program.file_hash.eq(i64::from(task.solution_hash)));
-                    q.filter(solution.problem.file_hash.eq(i64::from(task.problem_hash)));
-
-                    q.insert(ExecutionDummy {
-                        answer: q.select(&run_result.answer),
-                        fuel_used: q.select(run_result.fuel_used as i64),
-                        instance: q.select(instance),
-                        solution: q.select(solution),
-                        timestamp: q.select(UnixEpoch),
-                    });
-                });
-            } else {
-                conn.new_query(|q| {
-                    let solution = q.table(tables::Solution);
-                    q.filter(solution.program.file_hash.eq(i64::from(task.solution_hash)));
-                    q.filter(solution.problem.file_hash.eq(i64::from(task.problem_hash)));
-
-                    q.insert(FailureDummy {
-                        seed: q.select(task.instance_seed),
-                        solution: q.select(solution),
-                        timestamp: q.select(UnixEpoch),
-                    })
-                })
+            *benched && *random_tests < i64::from(problem.random_tests)
+        })
+        .map(|(candidate, _, _)| candidate)
+        .collect()
+}
+
+// stops the other threads of the bencher when a worker or the queue loop stops, also when it panics
+struct CloseOnExit<'a> {
+    queue: &'a TaskQueue,
+    conn: &'a SharedConnection,
+}
+
+impl Drop for CloseOnExit<'_> {
+    fn drop(&mut self) {
+        self.queue.close();
+        self.conn.notify();
+    }
+}
+
+fn bench_worker(app: &AppState, queue: &TaskQueue, engines: &Engines) -> anyhow::Result<()> {
+    let _close = CloseOnExit {
+        queue,
+        conn: &app.conn,
+    };
+    while let Some(task) = queue.take() {
+        let mut status = app.bencher.lock().unwrap();
+        status.set_queue(queue.pending());
+        status.last_task = Some(task.to_string());
+        drop(status);
+
+        let res = match task.test {
+            Test::Instance(seed) => run_instance(app, &task, seed, engines),
+            Test::Random => run_random_tests(app, &task, engines),
+        };
+        let last = queue.finish(&task);
+        match res {
+            Ok(true) => queue.fail(&task),
+            // the random tests of the solution can be queued now
+            Ok(false) if last && task.test != Test::Random => app.conn.notify(),
+            _ => {}
+        }
+        res?;
+    }
+    Ok(())
+}
+
+// these return `true` when the solution failed
+fn run_instance(
+    app: &AppState,
+    task: &QueuedTask,
+    seed: i64,
+    engines: &Engines,
+) -> anyhow::Result<bool> {
+    let problem_dir = app.problem_dir.get();
+    let Some(problem) = problem_dir.problems.get(&task.problem_hash) else {
+        // the problem was removed after the task was queued
+        return Ok(false);
+    };
+    let check = |instance: &TaskInstance| {
+        check_instance(&app.conn.lock(), task.problem_hash, seed, instance)
+    };
+    let instance = match app.instances.get(problem, &engines.problem, seed, check) {
+        Ok(Some(instance)) => instance,
+        Ok(None) => return Ok(false),
+        Err(error) => {
+            generator_error(app, task, seed, error);
+            // the instance is not queued again, a new one replaces it on the next sync
+            db::set_instance_invalid(&app.conn.lock(), task.problem_hash, seed)?;
+            return Ok(false);
+        }
+    };
+    let (fuel_used, failure) = bench(problem, task, &instance, engines);
+    let answer = Some(instance.answer);
+
+    // the connection lock makes sure that workers do not write at the same time
+    let conn = app.conn.lock();
+
+    if let Some(failure) = failure {
+        insert_failure(&conn, task, seed, &failure);
+        return Ok(true);
+    }
+
+    conn.new_query(|q| {
+        let instance = q.table(Instance);
+        q.filter(instance.problem.file_hash.eq(i64::from(task.problem_hash)));
+        q.filter(instance.seed.eq(seed));
+
+        let solution = q.table(tables::Solution);
+        q.filter(solution.program.file_hash.eq(i64::from(task.solution_hash)));
+        q.filter(solution.problem.file_hash.eq(i64::from(task.problem_hash)));
+
+        // `UNIQUE (instance, solution)` makes this a no-op if the task ran twice
+        q.insert(ExecutionDummy {
+            answer: q.select(&answer),
+            fuel_used: q.select(fuel_used as i64),
+            instance: q.select(instance),
+            solution: q.select(solution),
+            timestamp: q.select(UnixEpoch),
+        });
+    });
+    Ok(false)
+}
+
+/// Compares a freshly generated instance with the hash that was stored when it was created.
+/// Returns `false` and marks the instance invalid when the generator gave a different result.
+pub fn check_instance(
+    conn: &Connection,
+    problem_hash: FileHash,
+    seed: i64,
+    instance: &TaskInstance,
+) -> anyhow::Result<bool> {
+    let output_hash = instance.hash().to_string();
+    let stored = conn.new_query(|q| {
+        let instance = q.table(Instance);
+        q.filter(instance.problem.file_hash.eq(i64::from(problem_hash)));
+        q.filter(instance.seed.eq(seed));
+        q.into_vec(1, |row| row.get(instance.output_hash))
+    });
+
+    match stored.into_iter().next().flatten() {
+        Some(stored) if stored == output_hash => Ok(true),
+        // instances from before the hash was stored, or seeds that are not an instance
+        None => {
+            db::set_instance_hash(conn, problem_hash, seed, &output_hash)?;
+            Ok(true)
+        }
+        Some(_) => {
+            println!(
+                "problem {problem_hash} is not deterministic for seed {}",
+                seed as u64
+            );
+            db::set_instance_invalid(conn, problem_hash, seed)?;
+            Ok(false)
+        }
+    }
+}
+
+// a generator that fails for one seed should not stop the bencher, so the seed is skipped
+fn generator_error(app: &AppState, task: &QueuedTask, seed: i64, error: anyhow::Error) {
+    let error = format!(
+        "could not generate problem {} for seed {}: {error:#}",
+        task.problem_hash, seed as u64
+    );
+    println!("{error}");
+    app.bencher.lock().unwrap().last_error = Some(error);
+}
+
+// test the solution on fresh seeds, to catch solutions that only work on the leaderboard
+fn run_random_tests(app: &AppState, task: &QueuedTask, engines: &Engines) -> anyhow::Result<bool> {
+    let problem_dir = app.problem_dir.get();
+    let Some(problem) = problem_dir.problems.get(&task.problem_hash) else {
+        // the problem was removed after the task was queued
+        return Ok(false);
+    };
+    let passed = db::random_tests(&app.conn.lock(), task.problem_hash, task.solution_hash);
+
+    let mut rng = thread_rng();
+    for _ in passed..i64::from(problem.random_tests) {
+        let seed = rng.next_u64() as i64;
+        // random instances are not cached, they are only used once
+        let instance = match problem.generate(&engines.problem, seed) {
+            Ok(instance) => instance,
+            Err(error) => {
+                generator_error(app, task, seed, error);
+                continue;
             }
+        };
+        let (_, failure) = bench(problem, task, &instance, engines);
+
+        let conn = app.conn.lock();
+        if let Some(failure) = failure {
+            insert_failure(&conn, task, seed, &failure);
+            return Ok(true);
         }
+        db::add_random_test(&conn, task.problem_hash, task.solution_hash)?;
+    }
+    Ok(false)
+}
+
+// returns the fuel used and the failure if there was one
+fn bench(
+    problem: &Problem,
+    task: &QueuedTask,
+    instance: &TaskInstance,
+    engines: &Engines,
+) -> (u64, Option<FailureInfo>) {
+    let solution = Solution {
+        hash: task.solution_hash,
+    };
+
+    // every run uses its own `Store`, so workers do not share any wasm state
+    let run_result = solution.run(&engines.solution, &instance.input, &problem.limits());
+
+    match run_result {
+        Ok(res) => (res.fuel_used, check_answer(res.answer, instance.answer)),
+        // setup errors are recorded like any other failure, so they do not stop the bencher
+        Err(error) => (0, Some(FailureInfo::new(error.kind(), error.message()))),
+    }
+}
+
+fn insert_failure(conn: &Connection, task: &QueuedTask, seed: i64, failure: &FailureInfo) {
+    conn.new_query(|q| {
+        let solution = q.table(tables::Solution);
+        q.filter(solution.program.file_hash.eq(i64::from(task.solution_hash)));
+        q.filter(solution.problem.file_hash.eq(i64::from(task.problem_hash)));
+
+        q.insert(FailureDummy {
+            seed: q.select(seed),
+            kind: q.select(failure.kind),
+            message: q.select(&failure.message),
+            expected: q.select(&failure.expected),
+            answer: q.select(&failure.answer),
+            solution: q.select(solution),
+            timestamp: q.select(UnixEpoch),
+        })
+    })
+}
+
+fn check_answer(answer: Result<i64, Fault>, expected: i64) -> Option<FailureInfo> {
+    match answer {
+        Ok(answer) if answer == expected => None,
+        Ok(answer) => Some(FailureInfo {
+            kind: "wrong_answer",
+            message: None,
+            expected: Some(expected),
+            answer: Some(answer),
+        }),
+        Err(fault) => Some(FailureInfo::new(fault.kind(), fault.message())),
     }
 }
@@ -1,13 +1,125 @@
-use advent_of_wasm::solution::Solution;
-use wasmtime::{Config, Engine};
+//! Runs a local solution the same way the bencher does, to check it before uploading.
+//!
+//! usage: `exec [--config <problem.toml>] <file.wasm> <problem> [--seed <seed>]... [--input <file>]`
+//!
+//! Without seeds or input, the example seeds of the problem are used.
 
-fn main() {
-    let sol = Solution {
-        hash: "jm2mh0Qgr74".parse().unwrap(),
+use std::{env, fs, path::PathBuf, process::ExitCode, thread};
+
+use advent_of_wasm::{
+    cache::{EngineKind, ModuleCache},
+    problem::{ProblemDir, PROBLEM_CONFIG},
+    solution::{verify_wasm, Solution, EPOCH_TICK},
+    store::BlobStore,
+};
+use anyhow::Context;
+
+fn main() -> ExitCode {
+    match exec() {
+        Ok(true) => ExitCode::SUCCESS,
+        Ok(false) => ExitCode::FAILURE,
+        Err(e) => {
+            eprintln!("error: {e:#}");
+            ExitCode::FAILURE
+        }
+    }
+}
+
+// returns `false` when any of the runs failed
+fn exec() -> anyhow::Result<bool> {
+    let mut config = PathBuf::from(PROBLEM_CONFIG);
+    let mut seeds = vec![];
+    let mut input = None;
+    let mut positional = vec![];
+
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        let mut value = || {
+            args.next()
+                .with_context(|| format!("missing value for {arg}"))
+        };
+        match arg.as_str() {
+            "--config" => config = value()?.into(),
+            "--seed" => seeds.push(value()?.parse().context("seeds should be numbers")?),
+            "--input" => input = Some(value()?),
+            _ => positional.push(arg),
+        }
+    }
+    let [path, problem_name] = &positional[..] else {
+        anyhow::bail!("usage: exec [--config <problem.toml>] <file.wasm> <problem> [--seed <seed>]... [--input <file>]");
     };
 
-    let engine = Engine::new(Config::new().consume_fuel(true)).unwrap();
-    let data = "1234".as_bytes();
-    let res = sol.run(&engine, data, 10000);
-    println!("{:?}", res.answer);
+    let data = fs::read(path).with_context(|| format!("could not read {path}"))?;
+    verify_wasm(&data).map_err(|e| anyhow::anyhow!("{e}\n{}", e.help()))?;
+
+    let problem_dir = ProblemDir::new(&config)?;
+    let problem_hash = *problem_dir
+        .mapping
+        .get(problem_name)
+        .with_context(|| format!("unknown problem `{problem_name}`"))?;
+    let problem = &problem_dir.problems[&problem_hash];
+
+    // the solution is stored like an upload, so it is loaded the same way
+    let store = BlobStore::new(env::temp_dir().join("advent-of-wasm"));
+    let solution = Solution {
+        hash: store.write(&data)?,
+    };
+    let generators = ModuleCache::new(EngineKind::Problem, store.clone())?;
+    let modules = ModuleCache::new(EngineKind::Solution, store)?;
+
+    // this drives the wall-clock limit, like in the bencher
+    let engine = modules.engine().clone();
+    thread::spawn(move || loop {
+        thread::sleep(EPOCH_TICK);
+        engine.increment_epoch();
+    });
+
+    let mut tests = vec![];
+    if let Some(input) = &input {
+        let data = fs::read(input).with_context(|| format!("could not read {input}"))?;
+        tests.push((input.clone(), data, None));
+    }
+    if seeds.is_empty() && input.is_none() {
+        seeds = problem.example_seeds.clone();
+        if seeds.is_empty() {
+            anyhow::bail!("the problem has no example seeds, use --seed or --input");
+        }
+    }
+    for seed in seeds {
+        let instance = problem.generate(&generators, seed)?;
+        tests.push((
+            format!("seed {seed}"),
+            instance.input.into(),
+            Some(instance.answer),
+        ));
+    }
+
+    let mut passed = true;
+    for (name, input, expected) in tests {
+        // setup errors are failures too, the same as in the bencher
+        let (fuel_used, answer) = match solution.run(&modules, &input, &problem.limits()) {
+            Ok(res) => (
+                res.fuel_used,
+                res.answer.map_err(|f| (f.kind(), f.message())),
+            ),
+            Err(e) => (0, Err((e.kind(), e.message()))),
+        };
+        let verdict = match (answer, expected) {
+            (Ok(answer), Some(expected)) if answer == expected => "pass".to_owned(),
+            (Ok(answer), Some(expected)) => {
+                passed = false;
+                format!("fail, expected {expected} but got {answer}")
+            }
+            (Ok(answer), None) => format!("answer {answer}"),
+            (Err((kind, message)), _) => {
+                passed = false;
+                match message {
+                    Some(message) => format!("fail, {kind}: {message}"),
+                    None => format!("fail, {kind}"),
+                }
+            }
+        };
+        println!("{name}: {verdict} (fuel used {fuel_used})");
+    }
+    Ok(passed)
 }
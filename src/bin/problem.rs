@@ -0,0 +1,105 @@
+//! Tools for problem authors.
+//!
+//! usage: `problem verify [--config <problem.toml>] <problem|file.wasm> [--seed <seed>]...`
+//!
+//! `verify` checks the exports of the problem module, prints the instances for the given seeds
+//! (or the example seeds of the problem) and checks that generating them again gives the same result.
+//! The fuel used by the reference solution is shown as a baseline for the fuel limit.
+
+use std::{env, fs, path::PathBuf, process::ExitCode};
+
+use advent_of_wasm::problem::{generate_instance, verify_problem, ProblemDir, PROBLEM_CONFIG};
+use anyhow::Context;
+use wasmtime::{Config, Engine, Module, Store};
+
+// the reference solution does not have a fuel limit, but the store needs some fuel
+const BASELINE_FUEL: u64 = 1 << 40;
+// number of input bytes that are shown for every instance
+const PREVIEW_LEN: usize = 32;
+
+fn main() -> ExitCode {
+    let mut args = env::args().skip(1);
+    let res = match args.next().as_deref() {
+        Some("verify") => verify(args),
+        _ => Err(anyhow::anyhow!(
+            "usage: problem verify [--config <problem.toml>] <problem|file.wasm> [--seed <seed>]..."
+        )),
+    };
+    match res {
+        Ok(true) => ExitCode::SUCCESS,
+        Ok(false) => ExitCode::FAILURE,
+        Err(e) => {
+            eprintln!("error: {e:#}");
+            ExitCode::FAILURE
+        }
+    }
+}
+
+// returns `false` when generation is not deterministic
+fn verify(mut args: impl Iterator<Item = String>) -> anyhow::Result<bool> {
+    let mut config = PathBuf::from(PROBLEM_CONFIG);
+    let mut seeds = vec![];
+    let mut positional = vec![];
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--config" => config = args.next().context("missing value for --config")?.into(),
+            "--seed" => {
+                let seed = args.next().context("missing value for --seed")?;
+                seeds.push(seed.parse().context("seeds should be numbers")?);
+            }
+            _ => positional.push(arg),
+        }
+    }
+    let [problem] = &positional[..] else {
+        anyhow::bail!("usage: problem verify [--config <problem.toml>] <problem|file.wasm> [--seed <seed>]...");
+    };
+
+    // problems from the config also have example seeds
+    let path = match ProblemDir::new(&config) {
+        Ok(dir) if dir.mapping.contains_key(problem) => {
+            let problem = &dir.problems[&dir.mapping[problem]];
+            if seeds.is_empty() {
+                seeds = problem.example_seeds.clone();
+            }
+            problem.file_name.0.clone()
+        }
+        _ => PathBuf::from(problem),
+    };
+    if seeds.is_empty() {
+        anyhow::bail!("no seeds to generate, use --seed");
+    }
+
+    let data = fs::read(&path).with_context(|| format!("could not read {}", path.display()))?;
+    verify_problem(&data).map_err(|e| anyhow::anyhow!("{e}\n{}", e.help()))?;
+    println!("{} has the expected exports", path.display());
+
+    let engine = Engine::new(Config::new().consume_fuel(true))?;
+    let module = Module::from_binary(&engine, &data)?;
+    let generate = |seed: i64| {
+        let mut store = Store::new(&engine, ());
+        store.add_fuel(BASELINE_FUEL)?;
+        generate_instance(&mut store, &module, seed)
+    };
+
+    let mut deterministic = true;
+    for seed in seeds {
+        let (instance, fuel) = generate(seed)?;
+        let (again, _) = generate(seed)?;
+
+        let preview = &instance.input[..instance.input.len().min(PREVIEW_LEN)];
+        println!();
+        println!("seed {seed}");
+        println!(
+            "  input:  {} bytes {:?}",
+            instance.input.len(),
+            String::from_utf8_lossy(preview)
+        );
+        println!("  answer: {}", instance.answer);
+        println!("  fuel used by solution: {}", fuel.unwrap_or_default());
+        if again.input != instance.input || again.answer != instance.answer {
+            println!("  generating again gave a different instance!");
+            deterministic = false;
+        }
+    }
+    Ok(deterministic)
+}
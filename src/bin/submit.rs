@@ -0,0 +1,131 @@
+//! Uploads a solution and waits for the benchmark results.
+//!
+//! usage: `submit [--server <url>] [--token <token>] [--timeout <seconds>] <problem> <file.wasm>`
+//!
+//! The server and token can also be set with `AOW_SERVER` and `AOW_TOKEN`.
+//! Tokens are created on the profile page of the server.
+//! It stops with an error when there are no results after `--timeout` seconds (one hour by default).
+
+use std::{
+    env, fs,
+    process::ExitCode,
+    time::{Duration, Instant},
+};
+
+use anyhow::Context;
+use reqwest::{multipart, Client, Response};
+use serde_json::Value;
+
+const POLL_INTERVAL: Duration = Duration::from_secs(2);
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(60 * 60);
+
+#[tokio::main]
+async fn main() -> ExitCode {
+    match submit().await {
+        Ok(true) => ExitCode::SUCCESS,
+        Ok(false) => ExitCode::FAILURE,
+        Err(e) => {
+            eprintln!("error: {e:#}");
+            ExitCode::FAILURE
+        }
+    }
+}
+
+// returns `false` when the solution failed
+async fn submit() -> anyhow::Result<bool> {
+    let mut server = env::var("AOW_SERVER").unwrap_or("http://localhost:3000".to_owned());
+    let mut token = env::var("AOW_TOKEN").ok();
+    let mut timeout = DEFAULT_TIMEOUT;
+    let mut positional = vec![];
+
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--server" => server = args.next().context("missing value for --server")?,
+            "--token" => token = Some(args.next().context("missing value for --token")?),
+            "--timeout" => {
+                let secs = args.next().context("missing value for --timeout")?;
+                let secs = secs
+                    .parse()
+                    .context("--timeout should be a number of seconds")?;
+                timeout = Duration::from_secs(secs);
+            }
+            _ => positional.push(arg),
+        }
+    }
+    let [problem, path] = &positional[..] else {
+        anyhow::bail!(
+            "usage: submit [--server <url>] [--token <token>] [--timeout <seconds>] \
+            <problem> <file.wasm>"
+        );
+    };
+    let token = token.context("no token, set AOW_TOKEN or use --token")?;
+    let server = server.trim_end_matches('/');
+
+    let data = fs::read(path).with_context(|| format!("could not read {path}"))?;
+    let form =
+        multipart::Form::new().part("wasm", multipart::Part::bytes(data).file_name(path.clone()));
+
+    let client = Client::builder().user_agent("wasm-bench").build()?;
+    let url = format!("{server}/api/v1/problems/{problem}/solutions");
+    let response = client
+        .post(&url)
+        .bearer_auth(&token)
+        .multipart(form)
+        .send()
+        .await?;
+    let solution = json(response).await?["solution"]
+        .as_str()
+        .context("no solution hash in response")?
+        .to_owned();
+    println!("submitted {solution}");
+
+    let url = format!("{url}/{solution}");
+    let deadline = Instant::now() + timeout;
+    let mut last_progress = None;
+    loop {
+        let info = json(client.get(&url).send().await?).await?;
+        let executions = info["executions"].as_array().cloned().unwrap_or_default();
+        let total = info["total_instances"].as_i64().unwrap_or_default();
+
+        if let Some(reason) = info["failure"]["reason"].as_str() {
+            let seed = &info["failure"]["seed"];
+            println!("failed for seed {seed}: {reason}");
+            return Ok(false);
+        }
+
+        let progress = (executions.len(), info["queue_position"].as_u64());
+        if last_progress != Some(progress) {
+            match progress.1 {
+                Some(position) => println!("waiting for {position} other tasks"),
+                None => println!("benched {} / {total}", executions.len()),
+            }
+            last_progress = Some(progress);
+        }
+
+        if let Some(score) = info["score"].as_i64() {
+            println!();
+            println!("{:>20} {:>12}", "seed", "fuel");
+            for exec in &executions {
+                println!("{:>20} {:>12}", exec["seed"], exec["fuel_used"]);
+            }
+            println!("max fuel: {score}");
+            return Ok(true);
+        }
+        if Instant::now() >= deadline {
+            anyhow::bail!("no results after {} seconds", timeout.as_secs());
+        }
+        tokio::time::sleep(POLL_INTERVAL).await;
+    }
+}
+
+// the api returns `{ "error": .. }` with an error status
+async fn json(response: Response) -> anyhow::Result<Value> {
+    let status = response.status();
+    let value: Value = response.json().await.context("invalid response")?;
+    if !status.is_success() {
+        let error = value["error"].as_str().unwrap_or_default();
+        anyhow::bail!("server returned {status}: {error}");
+    }
+    Ok(value)
+}
@@ -0,0 +1,193 @@
+use std::{
+    collections::{hash_map::DefaultHasher, BTreeMap, HashMap},
+    hash::{Hash, Hasher},
+    sync::{Arc, Mutex},
+};
+
+use fehler::throws;
+use wasmtime::{Config, Engine, Module};
+
+use crate::{
+    hash::FileHash,
+    problem::{Problem, TaskInstance},
+    store::BlobStore,
+};
+
+// number of compiled modules that are kept in memory for every engine
+const MODULE_CACHE_SIZE: usize = 256;
+
+/// A map that evicts the least recently used entry when it is full.
+///
+/// `order` has the keys by the tick of their last use, so finding the oldest entry is cheap.
+pub struct Lru<K, V> {
+    capacity: usize,
+    tick: u64,
+    entries: HashMap<K, (V, u64)>,
+    order: BTreeMap<u64, K>,
+}
+
+impl<K: Hash + Eq + Clone, V: Clone> Lru<K, V> {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            tick: 0,
+            entries: HashMap::new(),
+            order: BTreeMap::new(),
+        }
+    }
+
+    pub fn get(&mut self, key: &K) -> Option<V> {
+        self.tick += 1;
+        let (value, last_used) = self.entries.get_mut(key)?;
+        // every entry has its tick in `order`
+        let key = self.order.remove(last_used).unwrap();
+        *last_used = self.tick;
+        self.order.insert(self.tick, key);
+        Some(value.clone())
+    }
+
+    pub fn insert(&mut self, key: K, value: V) {
+        self.tick += 1;
+        if let Some((_, last_used)) = self.entries.get(&key) {
+            self.order.remove(last_used);
+        } else if self.entries.len() >= self.capacity {
+            if let Some((_, oldest)) = self.order.pop_first() {
+                self.entries.remove(&oldest);
+            }
+        }
+        self.order.insert(self.tick, key.clone());
+        self.entries.insert(key, (value, self.tick));
+    }
+}
+
+/// The engine configs that are used, modules compiled for one config can not be used by another.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EngineKind {
+    /// counts fuel, used to run problem generators
+    Problem,
+    /// counts fuel and can be interrupted, used to run solutions
+    Solution,
+}
+
+impl EngineKind {
+    fn config(self) -> Config {
+        let mut config = Config::new();
+        // generators get fuel too, so a generator that loops forever can not block the server
+        config.consume_fuel(true);
+        if self == EngineKind::Solution {
+            config.epoch_interruption(true);
+        }
+        config
+    }
+
+    // this is part of the precompiled file name, it changes with the wasmtime version and config
+    fn tag(self, engine: &Engine) -> String {
+        let name = match self {
+            EngineKind::Problem => "problem",
+            EngineKind::Solution => "solution",
+        };
+        let mut hasher = DefaultHasher::new();
+        engine.precompile_compatibility_hash().hash(&mut hasher);
+        format!("{name}-{:016x}", hasher.finish())
+    }
+}
+
+/// An engine together with the modules that were compiled for it.
+pub struct ModuleCache {
+    engine: Engine,
+    tag: String,
+    store: BlobStore,
+    lru: Mutex<Lru<FileHash, Module>>,
+}
+
+impl ModuleCache {
+    #[throws(anyhow::Error)]
+    pub fn new(kind: EngineKind, store: BlobStore) -> Self {
+        let engine = Engine::new(&kind.config())?;
+        Self {
+            tag: kind.tag(&engine),
+            engine,
+            store,
+            lru: Mutex::new(Lru::new(MODULE_CACHE_SIZE)),
+        }
+    }
+
+    pub fn engine(&self) -> &Engine {
+        &self.engine
+    }
+
+    pub fn store(&self) -> &BlobStore {
+        &self.store
+    }
+
+    /// `wasm` is only called when there is no compiled module yet.
+    #[throws(anyhow::Error)]
+    pub fn load(&self, hash: FileHash, wasm: impl FnOnce() -> anyhow::Result<Vec<u8>>) -> Module {
+        if let Some(module) = self.lru.lock().unwrap().get(&hash) {
+            return module;
+        }
+        let module = self
+            .store
+            .load_module(&self.engine, &self.tag, hash, wasm)?;
+        self.lru.lock().unwrap().insert(hash, module.clone());
+        module
+    }
+}
+
+/// Generated instances, so that the generator only runs once for every seed.
+/// Instances that failed their check are cached as `None`.
+pub struct InstanceCache {
+    lru: Mutex<Lru<(FileHash, i64), Option<Arc<TaskInstance>>>>,
+}
+
+impl InstanceCache {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            lru: Mutex::new(Lru::new(capacity)),
+        }
+    }
+
+    /// `check` is called for every freshly generated instance, `None` is returned when it fails.
+    /// The result of the check is cached with the instance, an instance that was evicted is
+    /// generated and checked again.
+    #[throws(anyhow::Error)]
+    pub fn get(
+        &self,
+        problem: &Problem,
+        modules: &ModuleCache,
+        seed: i64,
+        check: impl FnOnce(&TaskInstance) -> anyhow::Result<bool>,
+    ) -> Option<Arc<TaskInstance>> {
+        let key = (problem.hash, seed);
+        if let Some(instance) = self.lru.lock().unwrap().get(&key) {
+            return instance;
+        }
+        // the lock is not held while generating, so other workers can continue
+        let instance = problem.generate(modules, seed)?;
+        let instance = check(&instance)?.then(|| Arc::new(instance));
+        self.lru.lock().unwrap().insert(key, instance.clone());
+        instance
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Lru;
+
+    #[test]
+    fn evict_least_recently_used() {
+        let mut lru = Lru::new(2);
+        lru.insert(1, "a");
+        lru.insert(2, "b");
+        assert_eq!(lru.get(&1), Some("a"));
+        lru.insert(3, "c");
+        assert_eq!(lru.get(&2), None);
+        assert_eq!(lru.get(&1), Some("a"));
+        assert_eq!(lru.get(&3), Some("c"));
+
+        // replacing a value does not evict anything
+        lru.insert(1, "d");
+        assert_eq!(lru.get(&1), Some("d"));
+        assert_eq!(lru.get(&3), Some("c"));
+    }
+}
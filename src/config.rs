@@ -0,0 +1,141 @@
+use std::{env, fs, path::PathBuf};
+
+use anyhow::Context;
+use fehler::throws;
+use serde::Deserialize;
+
+use crate::{auth::AUTH_CONFIG, problem::PROBLEM_CONFIG};
+
+const SERVER_CONFIG: &str = "config/server.toml";
+// environment variables are the option names in upper case with this prefix
+const ENV_PREFIX: &str = "AOW_";
+
+/// Settings of the server.
+///
+/// They are read from `config/server.toml` (or the file given with `--config`), then from
+/// environment variables like `AOW_DATABASE` and finally from flags like `--database`.
+/// Later sources override earlier ones.
+#[derive(Deserialize, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct ServerConfig {
+    pub database: PathBuf,
+    pub listen: String,
+    pub base_url: String, // public url of the server, used for login redirects
+    pub solution_dir: PathBuf,
+    pub problem_config: PathBuf,
+    pub auth_config: PathBuf,
+    pub bench_workers: Option<usize>, // defaults to the number of cores
+}
+
+impl Default for ServerConfig {
+    fn default() -> Self {
+        Self {
+            database: "test.db".into(),
+            listen: "0.0.0.0:3000".to_owned(),
+            base_url: "http://localhost:3000".to_owned(),
+            solution_dir: "solution".into(),
+            problem_config: PROBLEM_CONFIG.into(),
+            auth_config: AUTH_CONFIG.into(),
+            bench_workers: None,
+        }
+    }
+}
+
+impl ServerConfig {
+    #[throws(anyhow::Error)]
+    pub fn load() -> Self {
+        let args = parse_args(env::args().skip(1))?;
+
+        let explicit = args.iter().find(|(key, _)| key == "config");
+        let path = explicit.map_or(SERVER_CONFIG, |(_, path)| path.as_str());
+        let mut config: Self = match fs::read_to_string(path) {
+            Ok(content) => {
+                toml::from_str(&content).with_context(|| format!("could not parse {path}"))?
+            }
+            // the config file is optional, unless it was given explicitly
+            Err(_) if explicit.is_none() => Self::default(),
+            Err(e) => anyhow::bail!("could not read {path}: {e}"),
+        };
+        config.base_url = config.base_url.trim_end_matches('/').to_owned();
+
+        // from before there was a config
+        if let Ok(workers) = env::var("BENCH_WORKERS") {
+            config.set("bench-workers", &workers)?;
+        }
+        for (key, value) in env::vars() {
+            if let Some(key) = key.strip_prefix(ENV_PREFIX) {
+                config.set(&key.to_lowercase().replace('_', "-"), &value)?;
+            }
+        }
+
+        for (key, value) in &args {
+            if key != "config" {
+                config.set(key, value)?;
+            }
+        }
+        config
+    }
+
+    #[throws(anyhow::Error)]
+    fn set(&mut self, key: &str, value: &str) {
+        match key {
+            "database" => self.database = value.into(),
+            "listen" => self.listen = value.to_owned(),
+            "base-url" => self.base_url = value.trim_end_matches('/').to_owned(),
+            "solution-dir" => self.solution_dir = value.into(),
+            "problem-config" => self.problem_config = value.into(),
+            "auth-config" => self.auth_config = value.into(),
+            "bench-workers" => {
+                let workers = value.parse().context("bench-workers should be a number")?;
+                self.bench_workers = Some(workers);
+            }
+            _ => anyhow::bail!("unknown option `{key}`"),
+        }
+    }
+}
+
+// accepts both `--key value` and `--key=value`
+#[throws(anyhow::Error)]
+fn parse_args(mut args: impl Iterator<Item = String>) -> Vec<(String, String)> {
+    let mut res = vec![];
+    while let Some(arg) = args.next() {
+        let Some(arg) = arg.strip_prefix("--") else {
+            anyhow::bail!("unexpected argument `{arg}`");
+        };
+        let (key, value) = match arg.split_once('=') {
+            Some((key, value)) => (key.to_owned(), value.to_owned()),
+            None => {
+                let value = args
+                    .next()
+                    .with_context(|| format!("missing value for --{arg}"))?;
+                (arg.to_owned(), value)
+            }
+        };
+        res.push((key, value));
+    }
+    res
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parse_args, ServerConfig};
+
+    #[test]
+    fn later_sources_override() -> anyhow::Result<()> {
+        let args = [
+            "--listen",
+            "127.0.0.1:4000",
+            "--base-url=https://example.com/",
+        ];
+        let args = parse_args(args.into_iter().map(String::from))?;
+
+        let mut config = ServerConfig::default();
+        for (key, value) in &args {
+            config.set(key, value)?;
+        }
+        assert_eq!(config.listen, "127.0.0.1:4000");
+        assert_eq!(config.base_url, "https://example.com");
+        assert!(config.set("unknown", "").is_err());
+        Ok(())
+    }
+}
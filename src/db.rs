@@ -1,21 +1,21 @@
-use rusqlite::ToSql;
+use rusqlite::{params, params_from_iter, Connection};
 use rust_query::{
+    client::QueryBuilder,
     value::{Db, Value},
     Query,
 };
+use serde::Serialize;
 
 use crate::{
     hash::FileHash,
     tables::{self},
 };
 
-#[derive(Clone, Copy)]
-pub struct GithubId(pub i64);
-
-impl ToSql for GithubId {
-    fn to_sql(&self) -> rusqlite::Result<rusqlite::types::ToSqlOutput<'_>> {
-        self.0.to_sql()
-    }
+/// How a user is identified by the auth provider they logged in with.
+#[derive(Clone, Serialize)]
+pub struct UserKey {
+    pub provider: String,
+    pub subject: String,
 }
 
 pub fn get_file<'t>(q: &mut Query<'_, 't>, hash: FileHash) -> Db<'t, tables::File> {
@@ -24,8 +24,329 @@ pub fn get_file<'t>(q: &mut Query<'_, 't>, hash: FileHash) -> Db<'t, tables::Fil
     file
 }
 
-pub fn get_user<'t>(q: &mut Query<'_, 't>, github_id: GithubId) -> Db<'t, tables::User> {
+pub fn get_user<'t>(q: &mut Query<'_, 't>, key: &UserKey) -> Db<'t, tables::User> {
     let user = q.table(tables::User);
-    q.filter(user.github_id.eq(github_id.0));
+    q.filter(user.provider.eq(key.provider.as_str()));
+    q.filter(user.subject.eq(key.subject.as_str()));
     user
 }
+
+/// The benchmark results of a solution on the leaderboard instances of a problem.
+#[derive(Serialize)]
+pub struct SolutionResult {
+    pub program: FileHash,
+    pub file_size: u64,
+    pub failed: bool,
+    pub benched: i64,
+    pub total_instances: i64,
+    pub max_fuel: Option<i64>,
+}
+
+impl SolutionResult {
+    /// The score of the solution, this is only known after all instances ran successfully.
+    pub fn score(&self) -> Option<i64> {
+        if self.failed || self.benched != self.total_instances {
+            return None;
+        }
+        self.max_fuel
+    }
+}
+
+pub fn solution_results(conn: &Connection, problem_hash: FileHash) -> Vec<SolutionResult> {
+    conn.new_query(|q| {
+        let solution = q.table(tables::Solution);
+        q.filter(solution.problem.file_hash.eq(i64::from(problem_hash)));
+        let fail = q.query(|q| {
+            let failures = q.table(tables::Failure);
+            q.filter_on(&failures.solution, &solution);
+            q.group().exists()
+        });
+        let total_instances = q.query(|q| {
+            let instance = q.table(tables::Instance);
+            q.filter(instance.problem.file_hash.eq(i64::from(problem_hash)));
+            q.filter(instance.invalid.eq(0));
+            q.group().count_distinct(instance)
+        });
+        let (max_fuel, count) = q.query(|q| {
+            let exec = q.table(tables::Execution);
+            q.filter_on(&exec.solution, &solution);
+            q.filter(exec.instance.problem.file_hash.eq(i64::from(problem_hash)));
+            // instances with a nondeterministic generator do not count
+            q.filter(exec.instance.invalid.eq(0));
+            let group = &q.group();
+            (group.max(exec.fuel_used), group.count_distinct(exec))
+        });
+        q.into_vec(u32::MAX, |row| SolutionResult {
+            program: row.get(solution.program.file_hash).into(),
+            file_size: row.get(solution.program.file_size) as u64,
+            failed: row.get(fail),
+            benched: row.get(count),
+            total_instances: row.get(total_instances),
+            max_fuel: row.get(max_fuel),
+        })
+    })
+}
+
+/// Summary of the solutions for a problem, only solutions that passed all instances count for the records.
+#[derive(Serialize)]
+pub struct ProblemStats {
+    pub solutions: usize,
+    pub users: i64,
+    pub best_fuel: Option<i64>,
+    pub smallest_size: Option<u64>,
+}
+
+pub fn problem_stats(conn: &Connection, problem_hash: FileHash) -> ProblemStats {
+    let results = solution_results(conn, problem_hash);
+    let passed = || results.iter().filter(|r| r.score().is_some());
+
+    // like `recent_submissions`, this also counts users that submitted the file to another problem
+    let users = conn.new_query(|q| {
+        let count = q.query(|q| {
+            let solution = q.table(tables::Solution);
+            q.filter(solution.problem.file_hash.eq(i64::from(problem_hash)));
+            let submission = q.table(tables::Submission);
+            q.filter((&submission.solution).eq(&solution.program));
+            q.group().count_distinct(submission.user)
+        });
+        q.into_vec(1, |row| row.get(count))[0]
+    });
+
+    ProblemStats {
+        solutions: results.len(),
+        users,
+        best_fuel: passed().filter_map(|r| r.score()).min(),
+        smallest_size: passed().map(|r| r.file_size).min(),
+    }
+}
+
+#[derive(Serialize)]
+pub struct ExecutionResult {
+    pub seed: u64,
+    pub fuel_used: i64,
+}
+
+/// The leaderboard instances that the solution ran on.
+pub fn executions(
+    conn: &Connection,
+    problem_hash: FileHash,
+    solution_hash: FileHash,
+) -> Vec<ExecutionResult> {
+    conn.new_query(|q| {
+        let exec = q.table(tables::Execution);
+        q.filter(exec.instance.problem.file_hash.eq(i64::from(problem_hash)));
+        q.filter(exec.solution.program.file_hash.eq(i64::from(solution_hash)));
+        q.filter(exec.instance.invalid.eq(0));
+        q.into_vec(u32::MAX, |row| ExecutionResult {
+            seed: row.get(exec.instance.seed) as u64,
+            fuel_used: row.get(exec.fuel_used),
+        })
+    })
+}
+
+#[derive(Serialize)]
+pub struct SolutionFailure {
+    pub seed: u64,
+    pub kind: String,
+    pub message: Option<String>,
+    pub expected: Option<i64>,
+    pub answer: Option<i64>,
+}
+
+impl SolutionFailure {
+    pub fn reason(&self) -> String {
+        let message = self.message.as_deref().unwrap_or_default();
+        match self.kind.as_str() {
+            "wrong_answer" => format!(
+                "wrong answer, expected {} but got {}",
+                self.expected.unwrap_or_default(),
+                self.answer.unwrap_or_default()
+            ),
+            "trap" => format!("trapped with `{message}`"),
+            "out_of_fuel" => "ran out of fuel".to_owned(),
+            "out_of_memory" => "exceeded the memory limit".to_owned(),
+            "timeout" => "exceeded the time limit".to_owned(),
+            "setup_error" => format!("could not be run, {message}"),
+            "missing_export" => format!("export `{message}` is missing or has the wrong type"),
+            _ => "unknown reason".to_owned(),
+        }
+    }
+}
+
+/// The first test that the solution failed, if any.
+pub fn failure(
+    conn: &Connection,
+    problem_hash: FileHash,
+    solution_hash: FileHash,
+) -> Option<SolutionFailure> {
+    conn.new_query(|q| {
+        let failure = q.table(tables::Failure);
+        let solution = &failure.solution;
+        q.filter(solution.program.file_hash.eq(i64::from(solution_hash)));
+        q.filter(solution.problem.file_hash.eq(i64::from(problem_hash)));
+        q.into_vec(u32::MAX, |row| SolutionFailure {
+            seed: row.get(failure.seed) as u64,
+            kind: row.get(failure.kind),
+            message: row.get(failure.message),
+            expected: row.get(failure.expected),
+            answer: row.get(failure.answer),
+        })
+        .into_iter()
+        .next()
+    })
+}
+
+/// The number of random tests that the solution passed.
+pub fn random_tests(conn: &Connection, problem_hash: FileHash, solution_hash: FileHash) -> i64 {
+    conn.new_query(|q| {
+        let solution = q.table(tables::Solution);
+        q.filter(solution.program.file_hash.eq(i64::from(solution_hash)));
+        q.filter(solution.problem.file_hash.eq(i64::from(problem_hash)));
+        q.into_vec(1, |row| row.get(solution.random_tests))
+            .first()
+            .copied()
+            .unwrap_or_default()
+    })
+}
+
+/// The logins of the users that submitted the solution.
+pub fn submitters(conn: &Connection, solution_hash: FileHash) -> Vec<String> {
+    conn.new_query(|q| {
+        let submission = q.table(tables::Submission);
+        q.filter(submission.solution.file_hash.eq(i64::from(solution_hash)));
+        q.into_vec(u32::MAX, |row| {
+            // sort by timestamp
+            let _ = row.get(submission.timestamp);
+            row.get(submission.user.login)
+        })
+    })
+}
+
+pub struct UserInfo {
+    pub key: UserKey,
+    pub login: String,
+}
+
+pub fn users(conn: &Connection) -> Vec<UserInfo> {
+    conn.new_query(|q| {
+        let user = q.table(tables::User);
+        q.into_vec(u32::MAX, |row| UserInfo {
+            key: UserKey {
+                provider: row.get(user.provider),
+                subject: row.get(user.subject),
+            },
+            login: row.get(user.login),
+        })
+    })
+}
+
+#[derive(Serialize)]
+pub struct UserSolution {
+    pub problem: String,
+    pub version: FileHash,
+    pub solution: FileHash,
+}
+
+/// The solutions that the user submitted, for every version of the problem.
+pub fn user_solutions(conn: &Connection, key: &UserKey) -> Vec<UserSolution> {
+    conn.new_query(|q| {
+        let submission = q.table(tables::Submission);
+        q.filter(submission.user.provider.eq(key.provider.as_str()));
+        q.filter(submission.user.subject.eq(key.subject.as_str()));
+        let solution = q.table(tables::Solution);
+        q.filter((&solution.program).eq(&submission.solution));
+        let version = q.table(tables::ProblemVersion);
+        q.filter((&version.file).eq(&solution.problem));
+        q.into_vec(u32::MAX, |row| UserSolution {
+            problem: row.get(version.name),
+            version: row.get(solution.problem.file_hash).into(),
+            solution: row.get(solution.program.file_hash).into(),
+        })
+    })
+}
+
+pub struct RecentSubmission {
+    pub timestamp: i64,
+    pub user: String,
+    pub problem: FileHash,
+    pub solution: FileHash,
+}
+
+/// The newest submissions to the given problem versions, newest first.
+///
+/// Submissions do not store the problem, so they are found through the solutions of the problem.
+/// When the same file was also submitted to another problem, by anyone, it is listed under both.
+// rust-query can not sort or limit, so this is sql
+pub fn recent_submissions(
+    conn: &Connection,
+    problems: &[FileHash],
+    limit: usize,
+) -> rusqlite::Result<Vec<RecentSubmission>> {
+    let placeholders = vec!["?"; problems.len()].join(", ");
+    let mut stmt = conn.prepare(&format!(
+        "SELECT submission.timestamp, user.login, problem.file_hash, program.file_hash
+        FROM submission
+        JOIN user ON user.id = submission.user
+        JOIN solution ON solution.program = submission.solution
+        JOIN file AS program ON program.id = solution.program
+        JOIN file AS problem ON problem.id = solution.problem
+        WHERE problem.file_hash IN ({placeholders})
+        ORDER BY submission.timestamp DESC
+        LIMIT {limit}"
+    ))?;
+    let rows = stmt.query_map(params_from_iter(problems), |row| {
+        Ok(RecentSubmission {
+            timestamp: row.get(0)?,
+            user: row.get(1)?,
+            problem: row.get(2)?,
+            solution: row.get(3)?,
+        })
+    })?;
+    rows.collect()
+}
+
+// rust-query can only select and insert, so updates are sql that finds the rows by their file hashes.
+
+/// Counts one more passed random test for the solution.
+pub fn add_random_test(
+    conn: &Connection,
+    problem_hash: FileHash,
+    solution_hash: FileHash,
+) -> rusqlite::Result<()> {
+    conn.execute(
+        "UPDATE solution SET random_tests = random_tests + 1
+        WHERE problem = (SELECT id FROM file WHERE file_hash = ?1)
+        AND program = (SELECT id FROM file WHERE file_hash = ?2)",
+        params![problem_hash, solution_hash],
+    )?;
+    Ok(())
+}
+
+/// Stores the hash of the generated instance, for instances from before the hash was stored.
+pub fn set_instance_hash(
+    conn: &Connection,
+    problem_hash: FileHash,
+    seed: i64,
+    output_hash: &str,
+) -> rusqlite::Result<()> {
+    conn.execute(
+        "UPDATE instance SET output_hash = ?3
+        WHERE problem = (SELECT id FROM file WHERE file_hash = ?1) AND seed = ?2",
+        params![problem_hash, seed, output_hash],
+    )?;
+    Ok(())
+}
+
+/// Marks the instance as invalid, it is not benched anymore.
+pub fn set_instance_invalid(
+    conn: &Connection,
+    problem_hash: FileHash,
+    seed: i64,
+) -> rusqlite::Result<()> {
+    conn.execute(
+        "UPDATE instance SET invalid = 1
+        WHERE problem = (SELECT id FROM file WHERE file_hash = ?1) AND seed = ?2",
+        params![problem_hash, seed],
+    )?;
+    Ok(())
+}
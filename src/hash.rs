@@ -3,9 +3,9 @@ use std::{fmt::Display, str::FromStr};
 use base64::URL_SAFE_NO_PAD;
 use k12::digest::{ExtendableOutput, Update};
 use rusqlite::{types::*, ToSql};
-use serde::{de, Deserialize};
+use serde::{de, Deserialize, Serialize};
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
 pub struct FileHash([u8; 8]);
 
 impl FileHash {
@@ -18,6 +18,28 @@ impl FileHash {
     }
 }
 
+/// The full 256 bit hash of a file.
+///
+/// K12 is an extendable output function, so a `FileHash` is the prefix of the `ContentHash`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ContentHash([u8; 32]);
+
+impl ContentHash {
+    pub fn new(data: impl AsRef<[u8]>) -> Self {
+        let mut hasher = k12::KangarooTwelve::new();
+        hasher.update(data.as_ref());
+        let hash = hasher.finalize_boxed(32);
+        let hash = *Box::<[u8; 32]>::try_from(hash).unwrap();
+        Self(hash)
+    }
+}
+
+impl Display for ContentHash {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&base64::encode_config(self.0, URL_SAFE_NO_PAD))
+    }
+}
+
 impl From<FileHash> for i64 {
     fn from(value: FileHash) -> Self {
         i64::from_le_bytes(value.0)
@@ -67,3 +89,12 @@ impl<'de> Deserialize<'de> for FileHash {
         encoded.parse().map_err(de::Error::custom)
     }
 }
+
+impl Serialize for FileHash {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        serializer.collect_str(self)
+    }
+}
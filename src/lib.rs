@@ -1,3 +1,5 @@
+pub mod cache;
 pub mod hash;
 pub mod problem;
 pub mod solution;
+pub mod store;
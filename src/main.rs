@@ -1,82 +1,87 @@
-use std::sync::Arc;
+use std::sync::{Arc, Mutex};
 
+use auth::Providers;
+use bencher::BencherStatus;
+use cache::{EngineKind, InstanceCache, ModuleCache};
+use config::ServerConfig;
 use pages::web_server;
-use problem::ProblemDir;
-use rand::{thread_rng, RngCore};
+use problem::{ProblemDir, SharedProblemDir};
+use reload::sync_problems;
 use rusqlite::Connection;
 
 mod async_sqlite;
+mod auth;
 mod bencher;
+mod cache;
 mod chart;
+mod config;
 mod db;
 mod hash;
 mod migration;
 mod pages;
 mod problem;
+mod reload;
 mod solution;
+mod store;
 
 pub mod tables {
     include!(concat!(env!("OUT_DIR"), "/tables.rs"));
 }
 
 use async_sqlite::SharedConnection;
-use migration::initialize_db;
-use rust_query::{
-    client::QueryBuilder,
-    value::{UnixEpoch, Value},
-};
+use migration::{fill_full_hashes, initialize_db};
+use rust_query::client::QueryBuilder;
+use store::{BlobState, BlobStore};
 
-use crate::tables::{FileDummy, InstanceDummy};
+use crate::hash::FileHash;
 
 #[derive(Clone)]
 pub struct AppState {
-    problem_dir: Arc<ProblemDir>,
+    problem_dir: SharedProblemDir,
     conn: SharedConnection,
+    bencher: Arc<Mutex<BencherStatus>>,
+    instances: Arc<InstanceCache>,
+    generators: Arc<ModuleCache>, // used by the web pages, the bencher has its own engines
+    auth: Arc<Providers>,
+    config: Arc<ServerConfig>,
+    store: BlobStore,
 }
 
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
-    let mut conn = Connection::open("test.db")?;
+    let config = ServerConfig::load()?;
+    let mut conn = Connection::open(&config.database)?;
     initialize_db(&mut conn).expect("could not initialise db");
 
-    let problem_dir = Arc::new(ProblemDir::new()?);
-    for (file_hash, problem) in &problem_dir.problems {
-        let real_file_hash = problem.file_name.hash()?;
-        assert_eq!(file_hash.to_string(), real_file_hash.to_string());
+    let store = BlobStore::new(&config.solution_dir);
+    let generators = Arc::new(ModuleCache::new(EngineKind::Problem, store.clone())?);
 
-        conn.new_query(|q| {
-            // on conflict do nothing
-            q.insert(FileDummy {
-                timestamp: q.select(UnixEpoch),
-                file_hash: q.select(i64::from(*file_hash)),
-                file_size: q.select(problem.file_name.len().unwrap() as i64),
-            })
-        });
+    let problem_dir = ProblemDir::new(&config.problem_config)?;
+    problem_dir.check()?;
+    sync_problems(|| &conn, &problem_dir, &generators)?;
 
-        let num = conn.new_query(|q| {
-            let count = q.query(|q| {
-                let instance = q.table(tables::Instance);
-                q.filter(instance.problem.file_hash.eq(i64::from(*file_hash)));
-                q.group().count_distinct(instance)
-            });
-            q.into_vec(1, |row| row.get(count))[0]
-        });
+    check_store(&conn, &store);
+    fill_full_hashes(&conn, |hash| match problem_dir.problems.get(&hash) {
+        Some(problem) => problem.file_name.read(hash),
+        None => store.read(hash),
+    })?;
 
-        let mut rng = thread_rng();
-        // add instances so that there are enough for the benchmark
-        for _ in (0..problem.leaderboard_instances).skip(num as usize) {
-            let seed = rng.next_u64() as i64;
+    let problem_dir = SharedProblemDir::new(problem_dir);
+    web_server(config, problem_dir, conn, store, generators).await
+}
 
-            conn.new_query(|q| {
-                let problem = db::get_file(q, *file_hash);
-                q.insert(InstanceDummy {
-                    problem: q.select(problem),
-                    seed: q.select(seed),
-                    timestamp: q.select(UnixEpoch),
-                })
-            });
+// report solutions that can not be benchmarked because their file is missing or corrupted
+fn check_store(conn: &Connection, store: &BlobStore) {
+    let programs = conn.new_query(|q| {
+        let solution = q.table(tables::Solution);
+        q.into_vec(u32::MAX, |row| row.get(solution.program.file_hash))
+    });
+    for program in programs {
+        let hash = FileHash::from(program);
+        match store.check(hash) {
+            BlobState::Ok => {}
+            BlobState::Missing => println!("solution file {hash} is missing"),
+            BlobState::Corrupted => println!("solution file {hash} is corrupted"),
         }
     }
-
-    web_server(problem_dir, conn).await
 }
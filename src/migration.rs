@@ -1,8 +1,20 @@
-use rusqlite::Connection;
+use rusqlite::{params, Connection};
 use rusqlite_migration::{Migrations, M};
 
+use crate::hash::{ContentHash, FileHash};
+
 fn list_migrations() -> Migrations<'static> {
-    Migrations::new(vec![M::up(include_str!("migration/000_initial.sql"))])
+    Migrations::new(vec![
+        M::up(include_str!("migration/000_initial.sql")),
+        M::up(include_str!("migration/001_failure_kind.sql")),
+        M::up(include_str!("migration/002_failure_details.sql")),
+        M::up(include_str!("migration/003_full_hash.sql")),
+        M::up(include_str!("migration/004_problem_version.sql")),
+        M::up(include_str!("migration/005_session.sql")),
+        M::up(include_str!("migration/006_user_provider.sql")),
+        M::up(include_str!("migration/007_api_token.sql")),
+        M::up(include_str!("migration/008_instance_hash.sql")),
+    ])
 }
 
 pub fn initialize_db(conn: &mut Connection) -> anyhow::Result<()> {
@@ -15,6 +27,31 @@ pub fn initialize_db(conn: &mut Connection) -> anyhow::Result<()> {
     Ok(())
 }
 
+/// Fill in `file.full_hash` for files that were added before that column existed.
+pub fn fill_full_hashes(
+    conn: &Connection,
+    read: impl Fn(FileHash) -> anyhow::Result<Vec<u8>>,
+) -> anyhow::Result<()> {
+    let mut stmt = conn.prepare("SELECT file_hash FROM file WHERE full_hash IS NULL")?;
+    let hashes = stmt
+        .query_map([], |row| row.get::<_, FileHash>(0))?
+        .collect::<rusqlite::Result<Vec<_>>>()?;
+
+    for hash in hashes {
+        match read(hash) {
+            Ok(data) => {
+                let full_hash = ContentHash::new(data).to_string();
+                conn.execute(
+                    "UPDATE file SET full_hash = ?1 WHERE file_hash = ?2",
+                    params![full_hash, hash],
+                )?;
+            }
+            Err(e) => println!("could not fill in the full hash of {hash}: {e}"),
+        }
+    }
+    Ok(())
+}
+
 // Test that migrations are working
 #[cfg(test)]
 mod tests {
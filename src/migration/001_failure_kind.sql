@@ -0,0 +1,2 @@
+-- why the solution failed, failures from before this column are `unknown`
+ALTER TABLE failure ADD COLUMN kind TEXT NOT NULL DEFAULT 'unknown';
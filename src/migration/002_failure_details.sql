@@ -0,0 +1,5 @@
+-- trap message or the name of the missing export
+ALTER TABLE failure ADD COLUMN message TEXT;
+-- only set for wrong answers
+ALTER TABLE failure ADD COLUMN expected INTEGER;
+ALTER TABLE failure ADD COLUMN answer INTEGER;
@@ -0,0 +1,4 @@
+-- the full 256 bit hash of the file, `file_hash` is only the first 64 bits of it.
+-- this is null for files from before this column, until the server fills it in.
+ALTER TABLE file ADD COLUMN full_hash TEXT;
+CREATE UNIQUE INDEX file_full_hash ON file (full_hash);
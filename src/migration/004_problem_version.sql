@@ -0,0 +1,9 @@
+-- the problem files that were used for a problem name, the name stays the same
+-- when the problem file changes, so results of the old versions can still be found.
+CREATE TABLE problem_version (
+    id INTEGER PRIMARY KEY,
+    timestamp INTEGER NOT NULL DEFAULT (unixepoch('now')),
+    name TEXT NOT NULL,
+    file INTEGER NOT NULL REFERENCES file,
+    UNIQUE (name, file)
+) STRICT;
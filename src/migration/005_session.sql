@@ -0,0 +1,7 @@
+-- a logged in browser, `token` is stored in the session cookie
+CREATE TABLE session (
+    id INTEGER PRIMARY KEY,
+    timestamp INTEGER NOT NULL DEFAULT (unixepoch('now')),
+    token TEXT NOT NULL UNIQUE,
+    user INTEGER NOT NULL REFERENCES user
+) STRICT;
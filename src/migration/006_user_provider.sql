@@ -0,0 +1,18 @@
+-- users can log in with different auth providers, so they are not identified by a github id anymore.
+-- sqlite can not drop the not null constraint, so the table is rebuilt.
+CREATE TABLE user_new (
+    id INTEGER PRIMARY KEY,
+    timestamp INTEGER NOT NULL DEFAULT (unixepoch('now')),
+    -- the name of the auth provider in the config, e.g. "github"
+    provider TEXT NOT NULL,
+    -- the id of the user at the provider
+    subject TEXT NOT NULL,
+    login TEXT NOT NULL,
+    UNIQUE (provider, subject)
+) STRICT;
+
+INSERT INTO user_new (id, timestamp, provider, subject, login)
+SELECT id, timestamp, 'github', CAST(github_id AS TEXT), github_login FROM user;
+
+DROP TABLE user;
+ALTER TABLE user_new RENAME TO user;
@@ -0,0 +1,10 @@
+-- personal tokens to use the api without a browser.
+-- only the hash of the token is stored, `prefix` is shown to tell tokens apart.
+CREATE TABLE api_token (
+    id INTEGER PRIMARY KEY,
+    timestamp INTEGER NOT NULL DEFAULT (unixepoch('now')),
+    name TEXT NOT NULL,
+    prefix TEXT NOT NULL,
+    token_hash TEXT NOT NULL UNIQUE,
+    user INTEGER NOT NULL REFERENCES user
+) STRICT;
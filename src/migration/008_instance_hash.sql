@@ -0,0 +1,5 @@
+-- hash of the generated input and answer, the bencher checks that the generator still gives the same result.
+-- this is null for instances from before this column, until the bencher fills it in.
+ALTER TABLE instance ADD COLUMN output_hash TEXT;
+-- set when generating the instance gave a different result, these are not benched and do not count for scores.
+ALTER TABLE instance ADD COLUMN invalid INTEGER NOT NULL DEFAULT 0;
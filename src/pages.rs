@@ -1,55 +1,170 @@
-use std::{sync::Arc, thread};
+use std::{
+    sync::{Arc, Mutex},
+    thread,
+};
 
 use axum::{
+    http::StatusCode,
     routing::{get, post},
     Router,
 };
 use maud::{html, Markup};
 use rusqlite::Connection;
+use rust_query::{client::QueryBuilder, value::Value};
+use serde::Deserialize;
 
-use crate::{async_sqlite::SharedConnection, bencher::bencher_main, problem::ProblemDir, AppState};
+use crate::{
+    async_sqlite::SharedConnection,
+    auth::Providers,
+    bencher::{supervise_bencher, BencherStatus},
+    cache::{InstanceCache, ModuleCache},
+    config::ServerConfig,
+    hash::FileHash,
+    problem::SharedProblemDir,
+    reload::watch_problem_dir,
+    store::BlobStore,
+    tables, AppState,
+};
 
 use self::{
+    home::home,
     problem::{get_problem, upload},
+    status::{status, status_json},
     submission::submission,
 };
 
+mod api;
+mod home;
 mod login;
 mod problem;
+mod session;
+mod status;
 mod submission;
+mod token;
 
-pub async fn web_server(problem_dir: Arc<ProblemDir>, conn: Connection) -> anyhow::Result<()> {
+// number of generated instances that are kept in memory
+const INSTANCE_CACHE_SIZE: usize = 4096;
+
+pub async fn web_server(
+    config: ServerConfig,
+    problem_dir: SharedProblemDir,
+    conn: Connection,
+    store: BlobStore,
+    generators: Arc<ModuleCache>,
+) -> anyhow::Result<()> {
     let conn = SharedConnection::new(conn);
-    let app_state = AppState { problem_dir, conn };
+    let app_state = AppState {
+        problem_dir,
+        conn,
+        bencher: Arc::new(Mutex::new(BencherStatus::default())),
+        instances: Arc::new(InstanceCache::new(INSTANCE_CACHE_SIZE)),
+        generators,
+        auth: Arc::new(Providers::load(&config.auth_config, &config.base_url)?),
+        config: Arc::new(config),
+        store,
+    };
 
     // build our application with a single route
     let app = Router::new()
+        .route("/", get(home))
         .route("/problem/:problem", get(get_problem))
         .route("/problem/:problem", post(upload))
         .route("/problem/:problem/:solution_hash", get(submission))
         .route("/login", get(login::login))
-        .route("/redirect", get(login::redirect))
+        .route("/login/:provider", get(login::start))
+        .route("/login/:provider/callback", get(login::callback))
+        .route("/login/:provider/callback", post(login::callback_form))
+        .route("/me", get(session::me))
+        .route("/logout", get(session::logout))
+        .route("/me/tokens", post(token::create_token))
+        .route("/me/tokens/:id/revoke", post(token::revoke_token))
+        .route("/status", get(status))
+        .route("/status.json", get(status_json))
+        .nest("/api/v1", api::router())
         .with_state(app_state.clone());
 
-    // start the bencher
-    thread::spawn(|| bencher_main(app_state).unwrap());
-    // run out app with hyper on localhost:3000
-    let listener = tokio::net::TcpListener::bind("0.0.0.0:3000").await?;
+    // start the bencher, it is restarted when it crashes
+    let bencher_state = app_state.clone();
+    thread::spawn(|| supervise_bencher(bencher_state));
+    let listener = tokio::net::TcpListener::bind(&app_state.config.listen).await?;
+    // reload the problems when the config changes
+    thread::spawn(|| watch_problem_dir(app_state));
     axum::serve(listener, app).await?;
     Ok(())
 }
 
+#[derive(Deserialize)]
+pub struct VersionQuery {
+    // the current version of the problem is used when this is missing
+    version: Option<FileHash>,
+}
+
+impl VersionQuery {
+    // query string to keep looking at the same version
+    fn suffix(&self) -> String {
+        match self.version {
+            Some(version) => format!("?version={version}"),
+            None => String::new(),
+        }
+    }
+}
+
+struct ProblemVersion {
+    hash: FileHash,
+    timestamp: i64,
+}
+
+// all versions that a problem name has had, from old to new
+async fn get_versions(app: &AppState, problem: String) -> Vec<ProblemVersion> {
+    let mut versions = app
+        .conn
+        .call(move |conn| {
+            conn.new_query(|q| {
+                let version = q.table(tables::ProblemVersion);
+                q.filter(version.name.eq(problem.as_str()));
+                q.into_vec(u32::MAX, |row| ProblemVersion {
+                    hash: row.get(version.file.file_hash).into(),
+                    timestamp: row.get(version.timestamp),
+                })
+            })
+        })
+        .await;
+    versions.sort_by_key(|v| v.timestamp);
+    versions
+}
+
+// find the problem file that a page is about
+async fn resolve_version(
+    app: &AppState,
+    problem: &str,
+    query: &VersionQuery,
+) -> Result<FileHash, StatusCode> {
+    let Some(version) = query.version else {
+        let current = app.problem_dir.get().mapping.get(problem).copied();
+        return current.ok_or(StatusCode::NOT_FOUND);
+    };
+    let versions = get_versions(app, problem.to_owned()).await;
+    if !versions.iter().any(|v| v.hash == version) {
+        return Err(StatusCode::NOT_FOUND);
+    }
+    Ok(version)
+}
+
 enum Location {
+    Home,
+    Login,
+    Me,
     Problem(String, ProblemPage),
+    Status,
 }
 
 enum ProblemPage {
-    Home,
+    Home(String), // the title of the problem
+    Upload,
     Solution(String),
 }
 
 fn header(location: Location) -> Markup {
-    let Location::Problem(problem, page) = location;
     html! {
         head {
             link rel="stylesheet" href="https://cdn.simplecss.org/simple.css";
@@ -60,16 +175,43 @@ fn header(location: Location) -> Markup {
             script src="https://cdn.jsdelivr.net/npm/echarts-gl@2.0.9/dist/echarts-gl.js" {}
         }
         header {
-            @match page {
-                ProblemPage::Home => {
-                    h1 { "Problem " mark{(problem)} }
+            @match location {
+                Location::Home => {
+                    h1 { "Advent of Wasm" }
+                }
+                Location::Problem(_, ProblemPage::Home(title)) => {
+                    nav {
+                        a href="/" { "Problems" };
+                    }
+                    h1 { "Problem " mark{(title)} }
+                }
+                Location::Problem(problem, ProblemPage::Upload) => {
+                    nav {
+                        a href={"/problem/"(problem)} { (problem) };
+                    }
+                    h1 { "Upload" }
                 }
-                ProblemPage::Solution(solution) => {
+                Location::Problem(problem, ProblemPage::Solution(solution)) => {
                     nav {
                         a href={"/problem/"(problem)} { (problem) };
                     }
                     h1 { "Solution " mark{(solution)} }
                 }
+                Location::Login => {
+                    nav {
+                        a href="/" { "Problems" };
+                    }
+                    h1 { "Log In" }
+                }
+                Location::Me => {
+                    nav {
+                        a href="/" { "Problems" };
+                    }
+                    h1 { "Your Solutions" }
+                }
+                Location::Status => {
+                    h1 { "Bencher Status" }
+                }
             }
             // a href=""
         }
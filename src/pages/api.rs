@@ -0,0 +1,238 @@
+use axum::{
+    extract::{Multipart, Path, Query, State},
+    http::{HeaderMap, StatusCode},
+    response::{IntoResponse, Response},
+    routing::get,
+    Json, Router,
+};
+use axum_extra::extract::CookieJar;
+use serde::Serialize;
+use serde_json::json;
+
+use crate::{
+    db::{
+        executions, failure, problem_stats, random_tests, solution_results, submitters,
+        user_solutions, users, ExecutionResult, ProblemStats, SolutionFailure, SolutionResult,
+        UserSolution,
+    },
+    hash::FileHash,
+    pages::{
+        get_versions, problem::store_upload, resolve_version, token::request_user, VersionQuery,
+    },
+    problem::Difficulty,
+    AppState,
+};
+
+/// Version 1 of the JSON api, the responses use the same queries as the html pages.
+pub fn router() -> Router<AppState> {
+    Router::new()
+        .route("/problems", get(problems))
+        .route("/problems/:problem", get(problem))
+        .route("/problems/:problem/solutions", get(solutions).post(upload))
+        .route("/problems/:problem/solutions/:solution", get(solution))
+        .route("/users", get(all_users))
+}
+
+pub struct ApiError(StatusCode, String);
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        (self.0, Json(json!({ "error": self.1 }))).into_response()
+    }
+}
+
+impl From<StatusCode> for ApiError {
+    fn from(status: StatusCode) -> Self {
+        let reason = status.canonical_reason().unwrap_or_default();
+        Self(status, reason.to_lowercase())
+    }
+}
+
+#[derive(Serialize)]
+struct ProblemSummary {
+    name: String,
+    title: Option<String>,
+    version: FileHash,
+    #[serde(flatten)]
+    stats: ProblemStats,
+}
+
+async fn problems(State(app): State<AppState>) -> Json<Vec<ProblemSummary>> {
+    let problem_dir = app.problem_dir.get();
+    let mut problems: Vec<_> = problem_dir
+        .mapping
+        .iter()
+        .map(|(name, hash)| {
+            (
+                name.clone(),
+                problem_dir.problems[hash].title.clone(),
+                *hash,
+            )
+        })
+        .collect();
+    problems.sort();
+
+    let res = app
+        .conn
+        .call(move |conn| {
+            problems
+                .into_iter()
+                .map(|(name, title, version)| ProblemSummary {
+                    name,
+                    title,
+                    version,
+                    stats: problem_stats(conn, version),
+                })
+                .collect()
+        })
+        .await;
+    Json(res)
+}
+
+#[derive(Serialize)]
+struct ProblemInfo {
+    name: String,
+    version: FileHash,
+    // these are missing for old versions that are not in the config anymore
+    title: Option<String>,
+    difficulty: Option<Difficulty>,
+    tags: Vec<String>,
+    versions: Vec<FileHash>, // from old to new
+}
+
+async fn problem(
+    State(app): State<AppState>,
+    Path(name): Path<String>,
+    Query(query): Query<VersionQuery>,
+) -> Result<Json<ProblemInfo>, ApiError> {
+    let version = resolve_version(&app, &name, &query).await?;
+    let versions = get_versions(&app, name.clone()).await;
+
+    let problem_dir = app.problem_dir.get();
+    let info = problem_dir.problems.get(&version);
+    Ok(Json(ProblemInfo {
+        title: info.and_then(|info| info.title.clone()),
+        difficulty: info.and_then(|info| info.difficulty),
+        tags: info.map(|info| info.tags.clone()).unwrap_or_default(),
+        versions: versions.iter().map(|v| v.hash).collect(),
+        name,
+        version,
+    }))
+}
+
+async fn solutions(
+    State(app): State<AppState>,
+    Path(name): Path<String>,
+    Query(query): Query<VersionQuery>,
+) -> Result<Json<Vec<SolutionResult>>, ApiError> {
+    let version = resolve_version(&app, &name, &query).await?;
+    let res = app
+        .conn
+        .call(move |conn| solution_results(conn, version))
+        .await;
+    Ok(Json(res))
+}
+
+#[derive(Serialize)]
+struct SolutionInfo {
+    solution: FileHash,
+    version: FileHash,
+    users: Vec<String>,
+    random_tests: i64,
+    total_instances: i64,
+    score: Option<i64>, // known after all instances ran successfully
+    failure: Option<FailureInfo>,
+    queue_position: Option<usize>,
+    executions: Vec<ExecutionResult>,
+}
+
+#[derive(Serialize)]
+struct FailureInfo {
+    reason: String,
+    #[serde(flatten)]
+    failure: SolutionFailure,
+}
+
+async fn solution(
+    State(app): State<AppState>,
+    Path((name, solution)): Path<(String, String)>,
+    Query(query): Query<VersionQuery>,
+) -> Result<Json<SolutionInfo>, ApiError> {
+    let version = resolve_version(&app, &name, &query).await?;
+    let solution: FileHash = solution.parse().map_err(|_| StatusCode::NOT_FOUND)?;
+
+    let (result, executions, failure, random_tests, users) = app
+        .conn
+        .call(move |conn| {
+            let results = solution_results(conn, version);
+            (
+                results.into_iter().find(|r| r.program == solution),
+                executions(conn, version, solution),
+                failure(conn, version, solution),
+                random_tests(conn, version, solution),
+                submitters(conn, solution),
+            )
+        })
+        .await;
+    let Some(result) = result else {
+        return Err(StatusCode::NOT_FOUND.into());
+    };
+
+    let queue_position = app
+        .bencher
+        .lock()
+        .unwrap()
+        .queue_position(solution, version);
+    Ok(Json(SolutionInfo {
+        solution,
+        version,
+        users,
+        random_tests,
+        total_instances: result.total_instances,
+        score: result.score(),
+        failure: failure.map(|failure| FailureInfo {
+            reason: failure.reason(),
+            failure,
+        }),
+        queue_position,
+        executions,
+    }))
+}
+
+// the subject is the id at the login provider, it is not shown to others
+#[derive(Serialize)]
+struct UserEntry {
+    login: String,
+    solutions: Vec<UserSolution>,
+}
+
+async fn all_users(State(app): State<AppState>) -> Json<Vec<UserEntry>> {
+    let res = app
+        .conn
+        .call(|conn| {
+            users(conn)
+                .into_iter()
+                .map(|user| UserEntry {
+                    solutions: user_solutions(conn, &user.key),
+                    login: user.login,
+                })
+                .collect()
+        })
+        .await;
+    Json(res)
+}
+
+// the wasm file is uploaded as multipart form data in the `wasm` field
+async fn upload(
+    State(app): State<AppState>,
+    Path(name): Path<String>,
+    headers: HeaderMap,
+    jar: CookieJar,
+    multipart: Multipart,
+) -> Result<Json<serde_json::Value>, ApiError> {
+    let user = request_user(&app, &headers, &jar).await;
+    let solution = store_upload(&app, &name, user, multipart)
+        .await
+        .map_err(|e| ApiError(e.status(), e.message()))?;
+    Ok(Json(json!({ "solution": solution })))
+}
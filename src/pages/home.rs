@@ -0,0 +1,126 @@
+use std::{
+    collections::HashMap,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use axum::{extract::State, http::StatusCode, response::Html};
+use maud::html;
+
+use crate::{
+    db::{problem_stats, recent_submissions},
+    pages::{header, Location},
+    AppState,
+};
+
+// number of submissions shown on the home page
+const RECENT_SUBMISSIONS: usize = 20;
+
+pub async fn home(State(app): State<AppState>) -> Result<Html<String>, StatusCode> {
+    let problem_dir = app.problem_dir.get();
+    let mut problems: Vec<_> = problem_dir
+        .mapping
+        .iter()
+        .map(|(name, hash)| {
+            let title = problem_dir.problems[hash].title.clone();
+            (name.clone(), title.unwrap_or_else(|| name.clone()), *hash)
+        })
+        .collect();
+    problems.sort();
+
+    let stats = app
+        .conn
+        .call(move |conn| {
+            problems
+                .into_iter()
+                .map(|(name, title, hash)| (name, title, problem_stats(conn, hash)))
+                .collect::<Vec<_>>()
+        })
+        .await;
+
+    // solutions are copied to new problem versions, only show the current one
+    let names: HashMap<_, _> = problem_dir
+        .mapping
+        .iter()
+        .map(|(name, hash)| (*hash, name.clone()))
+        .collect();
+    let current: Vec<_> = names.keys().copied().collect();
+    let recent = app
+        .conn
+        .call(move |conn| recent_submissions(conn, &current, RECENT_SUBMISSIONS))
+        .await
+        .map_err(|e| {
+            println!("could not query recent submissions: {e}");
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+
+    let res = html! {
+        (header(Location::Home))
+        table {
+            thead {
+                tr {
+                    th { "Problem" }
+                    th { "Solutions" }
+                    th { "Users" }
+                    th { "Best Fuel" }
+                    th { "Smallest Size" }
+                }
+            }
+            tbody {
+                @for (name, title, problem) in &stats {
+                    tr {
+                        td { a href={"/problem/"(name)} { (title) } }
+                        td { (problem.solutions) }
+                        td { (problem.users) }
+                        td { (optional(problem.best_fuel)) }
+                        td { (optional(problem.smallest_size)) }
+                    }
+                }
+            }
+        }
+
+        h3 { "Recent Submissions" }
+        table {
+            thead {
+                tr {
+                    th { "When" }
+                    th { "User" }
+                    th { "Problem" }
+                    th { "Solution" }
+                }
+            }
+            tbody {
+                @for submission in &recent {
+                    @let problem = &names[&submission.problem];
+                    tr {
+                        td { (ago(submission.timestamp)) }
+                        td { (submission.user) }
+                        td { a href={"/problem/"(problem)} { (problem) } }
+                        td {
+                            a href={"/problem/"(problem)"/"(submission.solution)} {
+                                code { (submission.solution) }
+                            }
+                        }
+                    }
+                }
+            }
+        }
+    };
+    Ok(Html(res.into_string()))
+}
+
+fn optional(value: Option<impl ToString>) -> String {
+    value.map_or("-".to_owned(), |v| v.to_string())
+}
+
+fn ago(timestamp: i64) -> String {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_secs() as i64;
+    match (now - timestamp).max(0) {
+        s if s < 60 => format!("{s} seconds ago"),
+        s if s < 60 * 60 => format!("{} minutes ago", s / 60),
+        s if s < 60 * 60 * 24 => format!("{} hours ago", s / (60 * 60)),
+        s => format!("{} days ago", s / (60 * 60 * 24)),
+    }
+}
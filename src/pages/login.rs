@@ -1,92 +1,159 @@
-use std::fs;
+use std::collections::HashMap;
 
-use axum::extract::Query;
-use axum::http::StatusCode;
-use axum::response::Redirect;
-use axum_extra::extract::{cookie::Cookie, CookieJar};
-use oauth2::reqwest::async_http_client;
-use oauth2::TokenResponse;
-use oauth2::{
-    basic::BasicClient, AuthUrl, AuthorizationCode, ClientId, ClientSecret, CsrfToken, RedirectUrl,
-    TokenUrl,
+use axum::{
+    extract::{Path, Query, State},
+    http::StatusCode,
+    response::{Html, IntoResponse, Redirect, Response},
+    Form,
 };
-use serde::Deserialize;
+use axum_extra::extract::{
+    cookie::{Cookie, SameSite},
+    CookieJar,
+};
+use maud::html;
+use rusqlite::params;
+use rust_query::{client::QueryBuilder, value::UnixEpoch};
 
-#[derive(Deserialize)]
-pub struct Auth {
-    code: String,
-    state: String,
-}
+use crate::{
+    auth::LoginStart,
+    db::UserKey,
+    pages::{header, session::create_session, Location},
+    tables::UserDummy,
+    AppState,
+};
 
-#[derive(Deserialize)]
-struct GithubConfig {
-    github_client_id: String,
-    github_client_secret: String,
-}
+// the login is only finished when the callback has the state from this cookie
+const STATE_COOKIE: &str = "state";
 
-thread_local! {
-    static CONFIG: GithubConfig = {
-        let config = fs::read_to_string("config/oauth.toml").expect("could not read oath config");
-        toml::from_str(&config).expect("could not parse oauth config")
+// choose how to log in, this skips the choice when there is only one provider
+pub async fn login(State(app): State<AppState>) -> Response {
+    let names: Vec<_> = app.auth.names().collect();
+    if let [name] = names[..] {
+        return Redirect::to(&format!("/login/{name}")).into_response();
     }
-}
-
-fn make_client() -> BasicClient {
-    let github_client_id = CONFIG.with(|x| ClientId::new(x.github_client_id.clone()));
-    let github_client_secret = CONFIG.with(|x| ClientSecret::new(x.github_client_secret.clone()));
 
-    let auth_url = AuthUrl::new("https://github.com/login/oauth/authorize".to_string())
-        .expect("Invalid authorization endpoint URL");
-    let token_url = TokenUrl::new("https://github.com/login/oauth/access_token".to_string())
-        .expect("Invalid token endpoint URL");
+    let res = html! {
+        (header(Location::Login))
+        @if names.is_empty() {
+            p class="notice" { "Logging in is not configured on this server." }
+        }
+        ul {
+            @for name in &names {
+                li { a href={"/login/"(name)} { "Log in with " (name) } }
+            }
+        }
+    };
+    Html(res.into_string()).into_response()
+}
 
-    // Set up the config for the Github OAuth2 process.
+pub async fn start(
+    State(app): State<AppState>,
+    Path(provider): Path<String>,
+    mut jar: CookieJar,
+) -> Result<Response, StatusCode> {
+    let auth = app.auth.get(&provider).ok_or(StatusCode::NOT_FOUND)?;
+    let start = auth.start().await.map_err(|e| {
+        println!("could not start {provider} login: {e:#}");
+        StatusCode::BAD_GATEWAY
+    })?;
 
-    BasicClient::new(
-        github_client_id,
-        Some(github_client_secret),
-        auth_url,
-        Some(token_url),
-    )
-    // This example will be running its own server at localhost:8080.
-    // See below for the server implementation.
-    .set_redirect_uri(RedirectUrl::new("http://localhost:3000/redirect".to_string()).unwrap())
+    match start {
+        LoginStart::Redirect { url, state } => {
+            let secure = app.config.base_url.starts_with("https://");
+            // built like the session cookie, lax so it is sent on the redirect back from the provider
+            let cookie = Cookie::build((STATE_COOKIE, state))
+                .path("/login")
+                .http_only(true)
+                .secure(secure)
+                .same_site(SameSite::Lax);
+            jar = jar.add(cookie);
+            Ok((jar, Redirect::to(&url)).into_response())
+        }
+        LoginStart::Form => {
+            let res = html! {
+                (header(Location::Login))
+                form method="post" action={"/login/"(provider)"/callback"} {
+                    fieldset {
+                        legend { "Log in" }
+                        label { "Username" input type="text" name="username"; }
+                        label { "Password" input type="password" name="password"; }
+                        button { "Log in" };
+                    }
+                }
+            };
+            Ok(Html(res.into_string()).into_response())
+        }
+    }
 }
 
-pub async fn redirect(
-    Query(auth): Query<Auth>,
-    mut jar: CookieJar,
+// providers that redirect send the result in the query
+pub async fn callback(
+    State(app): State<AppState>,
+    Path(provider): Path<String>,
+    jar: CookieJar,
+    Query(params): Query<HashMap<String, String>>,
 ) -> Result<(CookieJar, Redirect), StatusCode> {
-    let code = AuthorizationCode::new(auth.code);
-    if auth.state != jar.get("state").unwrap().value() {
+    // the state must match the cookie, so that nobody else can start the login
+    let state = jar.get(STATE_COOKIE).map(|c| c.value());
+    if state.is_none() || params.get("state").map(String::as_str) != state {
         return Err(StatusCode::BAD_REQUEST);
     }
+    let jar = jar.remove(Cookie::build(STATE_COOKIE).path("/login"));
+    finish(app, provider, jar, params).await
+}
 
-    let client = make_client();
-    let token_res = client
-        .exchange_code(code)
-        .request_async(async_http_client)
-        .await;
-
-    println!("Github returned the following token:\n{:?}\n", token_res);
-
-    if let Ok(token) = token_res {
-        let token_str = token.access_token().secret();
-        jar = jar.add(Cookie::new("access_token", token_str.to_owned()));
-        jar = jar.remove(Cookie::from("state"));
-    }
-    Ok((jar, Redirect::to("/me")))
+// the login form is posted, there is no csrf token, see `dev` in the local auth config
+pub async fn callback_form(
+    State(app): State<AppState>,
+    Path(provider): Path<String>,
+    jar: CookieJar,
+    Form(params): Form<HashMap<String, String>>,
+) -> Result<(CookieJar, Redirect), StatusCode> {
+    finish(app, provider, jar, params).await
 }
 
-pub async fn login(mut jar: CookieJar) -> Result<(CookieJar, Redirect), StatusCode> {
-    let client = make_client();
+async fn finish(
+    app: AppState,
+    provider: String,
+    jar: CookieJar,
+    params: HashMap<String, String>,
+) -> Result<(CookieJar, Redirect), StatusCode> {
+    let auth = app.auth.get(&provider).ok_or(StatusCode::NOT_FOUND)?;
+    let user = auth.finish(&params).await.map_err(|e| {
+        println!("{provider} login failed: {e:#}");
+        StatusCode::UNAUTHORIZED
+    })?;
 
-    // Generate the authorization URL to which we'll redirect the user.
-    let (authorize_url, csrf_state) = client
-        .authorize_url(CsrfToken::new_random)
-        // This example is requesting access to the user's public repos and email.
-        .url();
-    jar = jar.add(Cookie::new("state", csrf_state.secret().to_owned()));
+    let key = UserKey {
+        provider,
+        subject: user.subject,
+    };
+    let login = user.login;
+    let secure = app.config.base_url.starts_with("https://");
+    // the user is only looked up once, the session refers to the user row
+    let session = app
+        .conn
+        .call(move |conn| {
+            conn.new_query(|q| {
+                q.insert(UserDummy {
+                    provider: q.select(key.provider.as_str()),
+                    subject: q.select(key.subject.as_str()),
+                    login: q.select(login.as_str()),
+                    timestamp: q.select(UnixEpoch),
+                })
+            });
+            // the login name can change at the provider
+            conn.execute(
+                "UPDATE user SET login = ?1 WHERE provider = ?2 AND subject = ?3",
+                params![login, key.provider, key.subject],
+            )?;
+            rusqlite::Result::Ok(create_session(conn, &key, secure))
+        })
+        .await
+        .map_err(|e| {
+            println!("could not update user: {e}");
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
 
-    Ok((jar, Redirect::to(authorize_url.as_str())))
+    Ok((jar.add(session), Redirect::to("/me")))
 }
@@ -1,27 +1,34 @@
-use std::fs;
-
 use axum::{
-    extract::{Multipart, Path, State},
+    extract::{Multipart, Path, Query, State},
     http::StatusCode,
-    response::{Html, Redirect},
+    response::Html,
 };
 use axum_extra::extract::CookieJar;
 use maud::{html, PreEscaped};
-use rust_query::{
-    client::QueryBuilder,
-    value::{UnixEpoch, Value},
-};
+use rust_query::{client::QueryBuilder, value::UnixEpoch};
 
 use crate::{
+    bencher::check_instance,
     chart::{Axis, Grid, Root, Series, Title, Tooltip},
-    db::{get_file, get_user, GithubId},
-    hash::{self, FileHash},
-    pages::{header, Location, ProblemPage},
-    solution::verify_wasm,
-    tables::{self, FileDummy, SolutionDummy, SubmissionDummy, UserDummy},
+    db::{get_file, get_user, solution_results},
+    hash::{ContentHash, FileHash},
+    pages::{
+        get_versions, header, resolve_version,
+        session::{current_user, SessionUser},
+        Location, ProblemPage, VersionQuery,
+    },
+    problem::TaskInstance,
+    solution::{verify_wasm, WasmError},
+    tables::{FileDummy, SolutionDummy, SubmissionDummy},
     AppState,
 };
 
+struct Example {
+    seed: i64,
+    input: String,
+    answer: i64,
+}
+
 struct SolutionStats {
     name: String,
     max_fuel: String,
@@ -31,52 +38,77 @@ struct SolutionStats {
 pub async fn get_problem(
     State(app): State<AppState>,
     Path(problem): Path<String>,
+    Query(query): Query<VersionQuery>,
     // uri: Uri,
 ) -> Result<Html<String>, StatusCode> {
     println!("got user for {problem}");
 
-    let problem_hash = *app
-        .problem_dir
-        .mapping
-        .get(&problem)
-        .ok_or(StatusCode::NOT_FOUND)?;
+    let problem_hash = resolve_version(&app, &problem, &query).await?;
+    let current = app.problem_dir.get().mapping.get(&problem).copied();
+    let versions = get_versions(&app, problem.clone()).await;
+    let suffix = query.suffix();
+
+    // old versions might not be in the config anymore
+    let problem_dir = app.problem_dir.get();
+    let info = problem_dir.problems.get(&problem_hash);
+    let title = info.and_then(|info| info.title.clone());
+    let statement = info.map(|info| info.statement_html()).transpose();
+    let statement = statement.map_err(|e| {
+        println!("{e:#}");
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+
+    let examples = {
+        let app = app.clone();
+        let problem_dir = problem_dir.clone();
+        tokio::task::spawn_blocking(move || {
+            let Some(info) = problem_dir.problems.get(&problem_hash) else {
+                return Ok(vec![]);
+            };
+            let mut examples = vec![];
+            for &seed in &info.example_seeds {
+                let check = |instance: &TaskInstance| {
+                    check_instance(&app.conn.lock(), problem_hash, seed, instance)
+                };
+                // the generator is not deterministic for this seed, so it is not shown
+                let Some(instance) = app.instances.get(info, &app.generators, seed, check)? else {
+                    continue;
+                };
+                examples.push(Example {
+                    seed,
+                    input: String::from_utf8_lossy(&instance.input).into_owned(),
+                    answer: instance.answer,
+                });
+            }
+            anyhow::Ok(examples)
+        })
+        .await
+        .map_err(anyhow::Error::from)
+        .and_then(|res| res)
+        .map_err(|e| {
+            println!("could not generate examples: {e:#}");
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?
+    };
 
     let data = app
         .conn
         .call(move |conn| {
             // list solutions for this problem
-            conn.new_query(|q| {
-                let solution = q.table(tables::Solution);
-                q.filter(solution.problem.file_hash.eq(i64::from(problem_hash)));
-                let fail = q.query(|q| {
-                    let failures = q.table(tables::Failure);
-                    q.filter_on(&failures.solution, &solution);
-                    q.group().exists()
-                });
-                let total_instances = q.query(|q| {
-                    let instance = q.table(tables::Instance);
-                    q.filter(instance.problem.file_hash.eq(i64::from(problem_hash)));
-                    q.group().count_distinct(instance)
-                });
-                let (max_fuel, count) = q.query(|q| {
-                    let exec = q.table(tables::Execution);
-                    q.filter_on(&exec.solution, &solution);
-                    q.filter(exec.instance.problem.file_hash.eq(i64::from(problem_hash)));
-                    let group = &q.group();
-                    (group.max(exec.fuel_used), group.count_distinct(exec))
-                });
-                q.into_vec(u32::MAX, |row| SolutionStats {
-                    file_size: row.get(solution.program.file_size) as u64,
-                    name: FileHash::from(row.get(solution.program.file_hash)).to_string(),
-                    max_fuel: if row.get(fail) {
+            solution_results(conn, problem_hash)
+                .into_iter()
+                .map(|result| SolutionStats {
+                    file_size: result.file_size,
+                    name: result.program.to_string(),
+                    max_fuel: if result.failed {
                         "Failed".to_owned()
-                    } else if row.get(count) == row.get(total_instances) {
-                        row.get(max_fuel).unwrap().to_string()
+                    } else if let Some(score) = result.score() {
+                        score.to_string()
                     } else {
-                        format!("benched {} / {}", row.get(count), row.get(total_instances))
+                        format!("benched {} / {}", result.benched, result.total_instances)
                     },
                 })
-            })
+                .collect::<Vec<_>>()
         })
         .await;
 
@@ -93,9 +125,37 @@ window.addEventListener('resize', function() {{
         serde_json::to_string(&chart_data).unwrap()
     );
 
-    let location = Location::Problem(problem.clone(), ProblemPage::Home);
+    let title = title.unwrap_or_else(|| problem.clone());
+    let location = Location::Problem(problem.clone(), ProblemPage::Home(title));
     let res = html! {
         (header(location))
+        @if current != Some(problem_hash) {
+            p class="notice" {
+                "These are the results for an old version of this problem."
+            }
+        }
+        @if let Some(info) = info {
+            p {
+                @if let Some(difficulty) = info.difficulty {
+                    "Difficulty: " strong {(difficulty)} " "
+                }
+                @for tag in &info.tags {
+                    mark {(tag)} " "
+                }
+            }
+        }
+        @if let Some(statement) = statement.flatten() {
+            section { (PreEscaped(statement)) }
+        }
+        @if !examples.is_empty() {
+            h3 { "Examples" }
+            @for example in &examples {
+                details {
+                    summary { "Seed " (example.seed) ", the answer is " code {(example.answer)} }
+                    pre { (example.input) }
+                }
+            }
+        }
         table {
             // caption { "Scores" }
             thead {
@@ -108,7 +168,7 @@ window.addEventListener('resize', function() {{
             tbody {
                 @for solution in &data {
                     tr {
-                        td { a href={(problem)"/"(solution.name)} { code{(solution.name)}} }
+                        td { a href={(problem)"/"(solution.name)(suffix)} { code{(solution.name)}} }
                         td {(solution.file_size)}
                         td {(solution.max_fuel)}
                     }
@@ -119,12 +179,31 @@ window.addEventListener('resize', function() {{
         div id="chart" style="height: 500px" {}
         script type="text/javascript" {(PreEscaped(js))}
 
-        form method="post" enctype="multipart/form-data" {
-            fieldset {
-                legend { "Submit a new program" }
-                aside { "Make sure to upload a " code {".wasm"} " file" }
-                input type="file" name="wasm";
-                button { "Submit!" };
+        @if current == Some(problem_hash) {
+            form method="post" enctype="multipart/form-data" {
+                fieldset {
+                    legend { "Submit a new program" }
+                    aside { "Make sure to upload a " code {".wasm"} " file" }
+                    input type="file" name="wasm";
+                    button { "Submit!" };
+                }
+            }
+        }
+
+        @if versions.len() > 1 {
+            details {
+                summary { "Versions" }
+                ul {
+                    @for version in versions.iter().rev() {
+                        li {
+                            @if Some(version.hash) == current {
+                                a href={"/problem/"(problem)} { code {(version.hash)} } " (current)"
+                            } @else {
+                                a href={"/problem/"(problem)"?version="(version.hash)} { code {(version.hash)} }
+                            }
+                        }
+                    }
+                }
             }
         }
     };
@@ -222,95 +301,203 @@ fn graph(data: &[SolutionStats]) -> Root {
     }
 }
 
+pub enum UploadError {
+    NotLoggedIn,
+    UnknownProblem,
+    NoFile,
+    Multipart(String),
+    Invalid(WasmError),
+    Store(String),
+}
+
+impl UploadError {
+    pub fn status(&self) -> StatusCode {
+        match self {
+            UploadError::NotLoggedIn => StatusCode::UNAUTHORIZED,
+            UploadError::UnknownProblem => StatusCode::NOT_FOUND,
+            UploadError::NoFile | UploadError::Multipart(_) | UploadError::Invalid(_) => {
+                StatusCode::BAD_REQUEST
+            }
+            UploadError::Store(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    pub fn message(&self) -> String {
+        match self {
+            UploadError::NotLoggedIn => "not logged in".to_owned(),
+            UploadError::UnknownProblem => "unknown problem".to_owned(),
+            UploadError::NoFile => "no file was uploaded in the `wasm` field".to_owned(),
+            UploadError::Multipart(e) => format!("could not read upload: {e}"),
+            UploadError::Invalid(e) => format!("{e}. {}", e.help()),
+            UploadError::Store(e) => format!("could not store file: {e}"),
+        }
+    }
+}
+
 pub async fn upload(
     State(app): State<AppState>,
     Path(file_name): Path<String>,
     jar: CookieJar,
-    mut multipart: Multipart,
-) -> Result<Redirect, String> {
+    multipart: Multipart,
+) -> (StatusCode, Html<String>) {
     println!("got multipart");
 
-    let access_token = jar.get("access_token").ok_or("not loged in")?;
-    let response = reqwest::Client::builder()
-        .user_agent("wasm-bench")
-        .build()
-        .unwrap()
-        .get("https://api.github.com/user")
-        .bearer_auth(access_token.value())
-        .header("X-GitHub-Api-Version", "2022-11-28")
-        .header("Accept", "application/vnd.github+json")
-        .send()
-        .await
-        .map_err(|_| "error connecting to github")?
-        .error_for_status()
-        .map_err(|_| "could not get github info, try loging in again")?;
-    let text = response.text().await.map_err(|_| "github response error")?;
-    println!("{}", text);
+    let user = current_user(&app, &jar).await;
+    let result = store_upload(&app, &file_name, user, multipart).await;
+    let status = result
+        .as_ref()
+        .err()
+        .map_or(StatusCode::OK, UploadError::status);
 
-    let val: serde_json::Value = serde_json::from_str(&text).unwrap();
+    let location = Location::Problem(file_name.clone(), ProblemPage::Upload);
+    let res = html! {
+        (header(location))
+        @match &result {
+            Ok(solution_hash) => {
+                p {
+                    "Your solution was submitted, it will be benchmarked soon."
+                }
+                p {
+                    a href={"/problem/"(file_name)"/"(solution_hash)} {
+                        "View solution " code {(solution_hash)}
+                    }
+                }
+            }
+            Err(UploadError::NotLoggedIn) => {
+                p class="notice" { "You need to be logged in to submit a solution." }
+                a href="/login" { "Log in" }
+            }
+            Err(UploadError::UnknownProblem) => {
+                p class="notice" { "The problem " code {(file_name)} " does not exist (anymore)." }
+            }
+            Err(UploadError::NoFile) => {
+                p class="notice" { "No file was uploaded." }
+            }
+            Err(UploadError::Multipart(e)) => {
+                p class="notice" { "The upload could not be read: " (e) }
+            }
+            Err(UploadError::Invalid(e)) => {
+                p class="notice" { "The file was rejected, " (e) "." }
+                p { (e.help()) }
+            }
+            Err(UploadError::Store(e)) => {
+                p class="notice" { "The file could not be stored: " (e) }
+            }
+        }
+        @if result.is_err() {
+            p { a href={"/problem/"(file_name)} { "Back to the problem" } }
+        }
+    };
+    (status, Html(res.into_string()))
+}
 
-    while let Some(field) = multipart.next_field().await.unwrap() {
-        let github_id = val.get("id").unwrap().as_i64().unwrap();
-        let github_id = GithubId(github_id);
-        let github_login = val.get("login").unwrap().as_str().unwrap().to_owned();
+pub async fn store_upload(
+    app: &AppState,
+    file_name: &str,
+    user: Option<SessionUser>,
+    mut multipart: Multipart,
+) -> Result<FileHash, UploadError> {
+    let user = user.ok_or(UploadError::NotLoggedIn)?;
+    let problem_hash = *app
+        .problem_dir
+        .get()
+        .mapping
+        .get(file_name)
+        .ok_or(UploadError::UnknownProblem)?;
 
-        let name = field.name().unwrap().to_string();
-        let data = field.bytes().await.unwrap();
-        let data_len = data.len();
+    // other fields are ignored
+    let data = loop {
+        let field = multipart
+            .next_field()
+            .await
+            .map_err(|e| UploadError::Multipart(e.body_text()))?
+            .ok_or(UploadError::NoFile)?;
+        if field.name() == Some("wasm") {
+            break field
+                .bytes()
+                .await
+                .map_err(|e| UploadError::Multipart(e.body_text()))?;
+        }
+    };
+    let data_len = data.len();
+    println!("Length of upload is {data_len} bytes");
+    if data.is_empty() {
+        return Err(UploadError::NoFile);
+    }
 
-        println!("Length of `{name}` is {data_len} bytes");
+    verify_wasm(&data).map_err(UploadError::Invalid)?;
 
-        if &name == "wasm" {
-            if let Err(e) = verify_wasm(&data) {
-                println!("user upload error: {}", e);
-                break;
-            }
+    // files from before `full_hash` existed are compared with the stored file instead,
+    // this has to happen before storing the upload, which would replace a missing file
+    let solution_hash = FileHash::new(&data);
+    let existing = app
+        .conn
+        .call(move |conn| {
+            conn.new_query(|q| {
+                let file = get_file(q, solution_hash);
+                q.into_vec(1, |row| row.get(file.full_hash))
+            })
+        })
+        .await;
+    if let Some(None) = existing.first() {
+        let stored = app.store.read(solution_hash).map_err(|e| {
+            UploadError::Store(format!("could not compare with file {solution_hash}: {e}"))
+        })?;
+        if stored != data {
+            let e = format!("hash collision with file {solution_hash}");
+            return Err(UploadError::Store(e));
+        }
+    }
 
-            let solution_hash = hash::FileHash::new(&data);
-            let path = format!("solution/{solution_hash}.wasm");
-            fs::write(path, data).unwrap();
+    let solution_hash = app
+        .store
+        .write(&data)
+        .map_err(|e| UploadError::Store(e.to_string()))?;
+    let full_hash = Some(ContentHash::new(&data).to_string());
+    let key = user.key;
 
-            let problem_hash = app.problem_dir.mapping[&file_name];
+    app.conn
+        .call(move |conn| {
+            // the short hash is used as id, so it must not be shared by different files
+            let existing = conn.new_query(|q| {
+                let file = get_file(q, solution_hash);
+                q.into_vec(1, |row| row.get(file.full_hash))
+            });
+            if let Some(Some(existing)) = existing.first() {
+                if Some(existing) != full_hash.as_ref() {
+                    let e = format!("hash collision with file {solution_hash}");
+                    return Err(UploadError::Store(e));
+                }
+            }
 
-            app.conn
-                .call(move |conn| {
-                    conn.new_query(|q| {
-                        q.insert(UserDummy {
-                            github_id: q.select(github_id.0),
-                            github_login: q.select(github_login.as_str()),
-                            timestamp: q.select(UnixEpoch),
-                        })
-                    });
-                    conn.new_query(|q| {
-                        q.insert(FileDummy {
-                            file_hash: q.select(i64::from(solution_hash)),
-                            file_size: q.select(data_len as i64),
-                            timestamp: q.select(UnixEpoch),
-                        })
-                    });
-                    conn.new_query(|q| {
-                        let problem = get_file(q, problem_hash);
-                        let program = get_file(q, solution_hash);
-                        q.insert(SolutionDummy {
-                            timestamp: q.select(UnixEpoch),
-                            program: q.select(program),
-                            problem: q.select(problem),
-                            random_tests: q.select(0),
-                        })
-                    });
-                    conn.new_query(|q| {
-                        let solution = get_file(q, solution_hash);
-                        let user = get_user(q, github_id);
-                        q.insert(SubmissionDummy {
-                            solution: q.select(solution),
-                            timestamp: q.select(UnixEpoch),
-                            user: q.select(user),
-                        })
-                    });
+            conn.new_query(|q| {
+                q.insert(FileDummy {
+                    file_hash: q.select(i64::from(solution_hash)),
+                    file_size: q.select(data_len as i64),
+                    full_hash: q.select(&full_hash),
+                    timestamp: q.select(UnixEpoch),
                 })
-                .await;
-        }
-    }
-
-    Ok(Redirect::to(""))
+            });
+            conn.new_query(|q| {
+                let problem = get_file(q, problem_hash);
+                let program = get_file(q, solution_hash);
+                q.insert(SolutionDummy {
+                    timestamp: q.select(UnixEpoch),
+                    program: q.select(program),
+                    problem: q.select(problem),
+                    random_tests: q.select(0),
+                })
+            });
+            conn.new_query(|q| {
+                let solution = get_file(q, solution_hash);
+                let user = get_user(q, &key);
+                q.insert(SubmissionDummy {
+                    solution: q.select(solution),
+                    timestamp: q.select(UnixEpoch),
+                    user: q.select(user),
+                })
+            });
+            Ok(solution_hash)
+        })
+        .await
 }
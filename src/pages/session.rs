@@ -0,0 +1,168 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use axum::{
+    extract::State,
+    http::StatusCode,
+    response::{Html, IntoResponse, Redirect, Response},
+};
+use axum_extra::extract::{
+    cookie::{Cookie, SameSite},
+    CookieJar,
+};
+use maud::html;
+use rand::{thread_rng, RngCore};
+use rusqlite::{params, Connection};
+use rust_query::{
+    client::QueryBuilder,
+    value::{UnixEpoch, Value},
+};
+
+use crate::{
+    db::{get_user, user_solutions, UserKey},
+    pages::{
+        header,
+        token::{list_tokens, token_list},
+        Location,
+    },
+    tables::{self, SessionDummy},
+    AppState,
+};
+
+const SESSION_COOKIE: &str = "session";
+// sessions are not valid anymore after this many seconds
+const SESSION_DURATION: i64 = 30 * 24 * 60 * 60;
+
+/// The user that is logged in with the session cookie.
+pub struct SessionUser {
+    pub key: UserKey,
+    pub login: String,
+}
+
+/// Creates a session for a user that is already in the database and returns the cookie for it.
+/// Browsers drop `secure` cookies for plain http, so it should only be set when the server uses https.
+pub fn create_session(conn: &Connection, key: &UserKey, secure: bool) -> Cookie<'static> {
+    let mut bytes = [0; 32];
+    thread_rng().fill_bytes(&mut bytes);
+    let token = base64::encode_config(bytes, base64::URL_SAFE_NO_PAD);
+
+    conn.new_query(|q| {
+        let user = get_user(q, key);
+        q.insert(SessionDummy {
+            timestamp: q.select(UnixEpoch),
+            token: q.select(token.as_str()),
+            user: q.select(user),
+        })
+    });
+
+    Cookie::build((SESSION_COOKIE, token))
+        .path("/")
+        .http_only(true)
+        .secure(secure)
+        // strict would drop the cookie on the redirect back from the login provider
+        .same_site(SameSite::Lax)
+        .build()
+}
+
+pub async fn current_user(app: &AppState, jar: &CookieJar) -> Option<SessionUser> {
+    let token = jar.get(SESSION_COOKIE)?.value().to_owned();
+    let (timestamp, provider, subject, login) = app
+        .conn
+        .call(move |conn| {
+            conn.new_query(|q| {
+                let session = q.table(tables::Session);
+                q.filter(session.token.eq(token.as_str()));
+                q.into_vec(1, |row| {
+                    (
+                        row.get(session.timestamp),
+                        row.get(session.user.provider),
+                        row.get(session.user.subject),
+                        row.get(session.user.login),
+                    )
+                })
+            })
+        })
+        .await
+        .pop()?;
+
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_secs() as i64;
+    if now - timestamp > SESSION_DURATION {
+        return None;
+    }
+    Some(SessionUser {
+        key: UserKey { provider, subject },
+        login,
+    })
+}
+
+pub async fn me(State(app): State<AppState>, jar: CookieJar) -> Result<Response, StatusCode> {
+    let Some(user) = current_user(&app, &jar).await else {
+        return Ok(Redirect::to("/login").into_response());
+    };
+
+    let key = user.key.clone();
+    let problem_dir = app.problem_dir.get();
+    let (mut solutions, tokens) = app
+        .conn
+        .call(move |conn| {
+            Ok::<_, rusqlite::Error>((user_solutions(conn, &key), list_tokens(conn, &key)?))
+        })
+        .await
+        .map_err(|e| {
+            println!("could not list tokens: {e}");
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+    // solutions are copied to new problem versions, only show the current one
+    solutions.retain(|s| problem_dir.mapping.get(&s.problem) == Some(&s.version));
+    solutions.sort_by_key(|s| (s.problem.clone(), s.solution.to_string()));
+
+    let res = html! {
+        (header(Location::Me))
+        p {
+            "Logged in as " strong { (user.login) } ". "
+            a href="/logout" { "Log out" }
+        }
+        table {
+            thead {
+                tr {
+                    th { "Problem" }
+                    th { "Solution" }
+                }
+            }
+            tbody {
+                @for solution in &solutions {
+                    tr {
+                        td { a href={"/problem/"(solution.problem)} { (solution.problem) } }
+                        td {
+                            a href={"/problem/"(solution.problem)"/"(solution.solution)} {
+                                code { (solution.solution) }
+                            }
+                        }
+                    }
+                }
+            }
+        }
+        (token_list(&tokens))
+    };
+    Ok(Html(res.into_string()).into_response())
+}
+
+pub async fn logout(
+    State(app): State<AppState>,
+    jar: CookieJar,
+) -> Result<(CookieJar, Redirect), StatusCode> {
+    if let Some(cookie) = jar.get(SESSION_COOKIE) {
+        let token = cookie.value().to_owned();
+        app.conn
+            .call(move |conn| conn.execute("DELETE FROM session WHERE token = ?1", params![token]))
+            .await
+            .map_err(|e| {
+                println!("could not delete session: {e}");
+                StatusCode::INTERNAL_SERVER_ERROR
+            })?;
+    }
+    let jar = jar.remove(Cookie::build(SESSION_COOKIE).path("/"));
+    Ok((jar, Redirect::to("/")))
+}
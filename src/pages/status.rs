@@ -0,0 +1,49 @@
+use axum::{extract::State, response::Html, Json};
+use maud::html;
+
+use crate::{
+    bencher::BencherStatus,
+    pages::{header, Location},
+    AppState,
+};
+
+pub async fn status(State(app): State<AppState>) -> Html<String> {
+    let status = app.bencher.lock().unwrap().clone();
+
+    let res = html! {
+        (header(Location::Status))
+        @if !status.running {
+            p class="notice" { "The bencher is not running, it will be restarted soon." }
+        }
+        table {
+            tbody {
+                tr {
+                    th { "Running" }
+                    td { (if status.running { "yes" } else { "no" }) }
+                }
+                tr {
+                    th { "Queue Length" }
+                    td { (status.queue_length) }
+                }
+                tr {
+                    th { "Last Task" }
+                    td { (status.last_task.as_deref().unwrap_or("none")) }
+                }
+                tr {
+                    th { "Crashes" }
+                    td { (status.crashes) }
+                }
+                tr {
+                    th { "Last Error" }
+                    td { code { (status.last_error.as_deref().unwrap_or("none")) } }
+                }
+            }
+        }
+    };
+    Html(res.into_string())
+}
+
+pub async fn status_json(State(app): State<AppState>) -> Json<BencherStatus> {
+    let status = app.bencher.lock().unwrap().clone();
+    Json(status)
+}
@@ -1,81 +1,45 @@
 use axum::{
-    extract::{Path, State},
+    extract::{Path, Query, State},
     http::StatusCode,
     response::Html,
 };
 use maud::html;
-use rust_query::{client::QueryBuilder, value::Value};
 
 use crate::{
+    db::{executions, failure, random_tests, submitters},
     hash::FileHash,
-    pages::{header, Location, ProblemPage},
-    tables, AppState,
+    pages::{header, resolve_version, Location, ProblemPage, VersionQuery},
+    AppState,
 };
 
 // information about a solution and its performance on a problem
 pub async fn submission(
     State(app): State<AppState>,
     Path((problem, solution_hash)): Path<(String, String)>,
+    Query(query): Query<VersionQuery>,
 ) -> Result<Html<String>, StatusCode> {
     println!("got user for {problem}");
 
-    let problem_hash = *app
-        .problem_dir
-        .mapping
-        .get(&problem)
-        .ok_or(StatusCode::NOT_FOUND)?;
+    let problem_hash = resolve_version(&app, &problem, &query).await?;
     let solution_hash: FileHash = solution_hash.parse().map_err(|_| StatusCode::NOT_FOUND)?;
 
-    struct SolutionStats {
-        seed: u64,
-        fuel: i64,
-    }
-
-    let data = app
+    let (data, failure, random_tests, users) = app
         .conn
         .call(move |conn| {
-            // list solutions for this problem
-            conn.new_query(|q| {
-                let exec = q.table(tables::Execution);
-                q.filter(exec.instance.problem.file_hash.eq(i64::from(problem_hash)));
-                q.filter(exec.solution.program.file_hash.eq(i64::from(solution_hash)));
-                q.into_vec(u32::MAX, |row| SolutionStats {
-                    seed: row.get(exec.instance.seed) as u64,
-                    fuel: row.get(exec.fuel_used),
-                })
-            })
+            (
+                executions(conn, problem_hash, solution_hash),
+                failure(conn, problem_hash, solution_hash),
+                random_tests(conn, problem_hash, solution_hash),
+                submitters(conn, solution_hash),
+            )
         })
         .await;
 
-    let failure = app
-        .conn
-        .call(move |conn| {
-            conn.new_query(|q| {
-                let failure = q.table(tables::Failure);
-                let solution = &failure.solution;
-                q.filter(solution.program.file_hash.eq(i64::from(solution_hash)));
-                q.filter(solution.problem.file_hash.eq(i64::from(problem_hash)));
-                q.into_vec(u32::MAX, |row| row.get(failure.seed))
-                    .first()
-                    .copied()
-            })
-        })
-        .await;
-
-    let users = app
-        .conn
-        .call(move |conn| {
-            conn.new_query(|q| {
-                let submission = q.table(tables::Submission);
-                q.filter(submission.solution.file_hash.eq(i64::from(solution_hash)));
-                q.into_vec(u32::MAX, |row| {
-                    // sort by timestamp
-                    let _ = row.get(submission.timestamp);
-                    row.get(submission.user.github_login)
-                })
-            })
-        })
-        .await;
+    let queue_position = app
+        .bencher
+        .lock()
+        .unwrap()
+        .queue_position(solution_hash, problem_hash);
 
     let location = Location::Problem(
         problem.clone(),
@@ -83,14 +47,22 @@ pub async fn submission(
     );
     let res = html! {
         (header(location))
-        @if let Some(seed) = failure {
+        @if let Some(failure) = failure {
             p class="notice" {
-                "Failed for seed " (seed as u64)
+                "Failed for seed " (failure.seed) ": " (failure.reason())
             }
         }
         p {
             "Discovered by " (users.join(", "))
         }
+        p {
+            "Passed " (random_tests) " random tests"
+        }
+        @if let Some(position) = queue_position {
+            p {
+                "Waiting for " (position) " other tasks in the bench queue"
+            }
+        }
         table {
             // caption { "Scores" }
             thead {
@@ -103,7 +75,7 @@ pub async fn submission(
                 @for solution in &data {
                     tr {
                         td {(solution.seed)}
-                        td {(solution.fuel)}
+                        td {(solution.fuel_used)}
                     }
                 }
             }
@@ -0,0 +1,211 @@
+use axum::{
+    extract::{Path, State},
+    http::{header::AUTHORIZATION, HeaderMap, StatusCode},
+    response::{Html, Redirect},
+    Form,
+};
+use axum_extra::extract::CookieJar;
+use maud::{html, Markup};
+use rand::{thread_rng, RngCore};
+use rusqlite::{params, Connection};
+use rust_query::{
+    client::QueryBuilder,
+    value::{UnixEpoch, Value},
+};
+use serde::Deserialize;
+
+use crate::{
+    db::{get_user, UserKey},
+    hash::ContentHash,
+    pages::{
+        header,
+        session::{current_user, SessionUser},
+        Location,
+    },
+    tables::{self, ApiTokenDummy},
+    AppState,
+};
+
+const TOKEN_PREFIX: &str = "aow_";
+// number of characters of the token that are stored and shown in the list
+const SHOWN_LEN: usize = TOKEN_PREFIX.len() + 6;
+
+pub struct TokenInfo {
+    pub id: i64, // prefixes are not unique, so tokens are revoked by id
+    pub name: String,
+    pub prefix: String,
+}
+
+// rust-query does not give us the id, so this is sql
+pub fn list_tokens(conn: &Connection, key: &UserKey) -> rusqlite::Result<Vec<TokenInfo>> {
+    let mut stmt = conn.prepare(
+        "SELECT api_token.id, api_token.name, api_token.prefix
+        FROM api_token
+        JOIN user ON user.id = api_token.user
+        WHERE user.provider = ?1 AND user.subject = ?2
+        ORDER BY api_token.timestamp",
+    )?;
+    let rows = stmt.query_map(params![key.provider, key.subject], |row| {
+        Ok(TokenInfo {
+            id: row.get(0)?,
+            name: row.get(1)?,
+            prefix: row.get(2)?,
+        })
+    })?;
+    rows.collect()
+}
+
+/// The user of an `Authorization: Bearer` token, or of the session cookie when there is no token.
+pub async fn request_user(
+    app: &AppState,
+    headers: &HeaderMap,
+    jar: &CookieJar,
+) -> Option<SessionUser> {
+    let Some(auth) = headers.get(AUTHORIZATION) else {
+        return current_user(app, jar).await;
+    };
+    let token = auth.to_str().ok()?.strip_prefix("Bearer ")?;
+    let token_hash = ContentHash::new(token.trim()).to_string();
+
+    let (provider, subject, login) = app
+        .conn
+        .call(move |conn| {
+            conn.new_query(|q| {
+                let token = q.table(tables::ApiToken);
+                q.filter(token.token_hash.eq(token_hash.as_str()));
+                q.into_vec(1, |row| {
+                    (
+                        row.get(token.user.provider),
+                        row.get(token.user.subject),
+                        row.get(token.user.login),
+                    )
+                })
+            })
+        })
+        .await
+        .pop()?;
+    Some(SessionUser {
+        key: UserKey { provider, subject },
+        login,
+    })
+}
+
+#[derive(Deserialize)]
+pub struct NewToken {
+    name: String,
+}
+
+// the token is only shown once, after that only the hash is known
+pub async fn create_token(
+    State(app): State<AppState>,
+    jar: CookieJar,
+    Form(new): Form<NewToken>,
+) -> Result<Html<String>, Redirect> {
+    let Some(user) = current_user(&app, &jar).await else {
+        return Err(Redirect::to("/login"));
+    };
+
+    let mut bytes = [0; 32];
+    thread_rng().fill_bytes(&mut bytes);
+    let token = format!(
+        "{TOKEN_PREFIX}{}",
+        base64::encode_config(bytes, base64::URL_SAFE_NO_PAD)
+    );
+    let token_hash = ContentHash::new(&token).to_string();
+    let prefix = token[..SHOWN_LEN].to_owned();
+    let name = match new.name.trim() {
+        "" => "unnamed".to_owned(),
+        name => name.to_owned(),
+    };
+
+    app.conn
+        .call(move |conn| {
+            conn.new_query(|q| {
+                let user = get_user(q, &user.key);
+                q.insert(ApiTokenDummy {
+                    timestamp: q.select(UnixEpoch),
+                    name: q.select(name.as_str()),
+                    prefix: q.select(prefix.as_str()),
+                    token_hash: q.select(token_hash.as_str()),
+                    user: q.select(user),
+                })
+            })
+        })
+        .await;
+
+    let res = html! {
+        (header(Location::Me))
+        p class="notice" {
+            "Copy your new token now, it will not be shown again."
+        }
+        pre { (token) }
+        p {
+            "Use it with the " code { "submit" } " binary or as "
+            code { "Authorization: Bearer <token>" } " header for the api."
+        }
+        a href="/me" { "Back to your profile" }
+    };
+    Ok(Html(res.into_string()))
+}
+
+pub async fn revoke_token(
+    State(app): State<AppState>,
+    jar: CookieJar,
+    Path(id): Path<i64>,
+) -> Result<Redirect, StatusCode> {
+    let Some(user) = current_user(&app, &jar).await else {
+        return Ok(Redirect::to("/login"));
+    };
+    app.conn
+        .call(move |conn| {
+            conn.execute(
+                "DELETE FROM api_token WHERE id = ?1 AND user IN
+                    (SELECT id FROM user WHERE provider = ?2 AND subject = ?3)",
+                params![id, user.key.provider, user.key.subject],
+            )
+        })
+        .await
+        .map_err(|e| {
+            println!("could not revoke token: {e}");
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+    Ok(Redirect::to("/me"))
+}
+
+/// The token section of the profile page.
+pub fn token_list(tokens: &[TokenInfo]) -> Markup {
+    html! {
+        h3 { "API Tokens" }
+        @if !tokens.is_empty() {
+            table {
+                thead {
+                    tr {
+                        th { "Name" }
+                        th { "Token" }
+                        th {}
+                    }
+                }
+                tbody {
+                    @for token in tokens {
+                        tr {
+                            td { (token.name) }
+                            td { code { (token.prefix) "…" } }
+                            td {
+                                form method="post" action={"/me/tokens/"(token.id)"/revoke"} {
+                                    button { "Revoke" }
+                                }
+                            }
+                        }
+                    }
+                }
+            }
+        }
+        form method="post" action="/me/tokens" {
+            fieldset {
+                legend { "Create a new token" }
+                input type="text" name="name" placeholder="name";
+                button { "Create" };
+            }
+        }
+    }
+}
@@ -1,18 +1,80 @@
 use anyhow::Context;
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 
-use std::{collections::HashMap, fs, path::PathBuf};
+use std::{
+    collections::HashMap,
+    fmt::Display,
+    fs,
+    path::{Path, PathBuf},
+    sync::{Arc, RwLock},
+    time::Duration,
+};
 
-use fehler::throws;
-use wasmtime::{Engine, Linker, Module, Store, TypedFunc};
+use fehler::{throw, throws};
+use wasmtime::{Engine, FuncType, Linker, Module, Store, TypedFunc, ValType};
 
-use crate::hash::FileHash;
+use crate::{
+    cache::ModuleCache,
+    hash::{ContentHash, FileHash},
+    solution::{check_imports, Limits, WasmError},
+};
 
 #[derive(Deserialize)]
 pub struct Problem {
+    #[serde(skip)]
+    pub hash: FileHash, // this is filled in from the key in `ProblemDir.problems`
     pub file_name: ModulePath,
     pub leaderboard_instances: u32, // this is how many of the oldest instances need to be ran
     pub fuel_limit: u64,
+    #[serde(default = "default_memory_limit")]
+    pub memory_limit: usize, // in bytes, includes the memory used by the input
+    #[serde(default = "default_time_limit_ms")]
+    pub time_limit_ms: u64,
+    #[serde(default = "default_random_tests")]
+    pub random_tests: u32, // fresh seeds to test after the leaderboard instances passed
+    #[serde(default = "default_generator_fuel")]
+    pub generator_fuel: u64, // for `generate` and `solution` of the problem together
+    pub title: Option<String>, // the name from `ProblemDir.mapping` is shown when this is missing
+    pub statement: Option<PathBuf>, // markdown file that explains what `solve` should compute
+    pub difficulty: Option<Difficulty>,
+    #[serde(default)]
+    pub tags: Vec<String>,
+    #[serde(default)]
+    pub example_seeds: Vec<i64>, // instances that are shown on the problem page
+}
+
+#[derive(Deserialize, Serialize, Clone, Copy)]
+#[serde(rename_all = "lowercase")]
+pub enum Difficulty {
+    Easy,
+    Medium,
+    Hard,
+}
+
+impl Display for Difficulty {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Difficulty::Easy => "Easy",
+            Difficulty::Medium => "Medium",
+            Difficulty::Hard => "Hard",
+        })
+    }
+}
+
+fn default_memory_limit() -> usize {
+    64 << 20
+}
+
+fn default_time_limit_ms() -> u64 {
+    10_000
+}
+
+fn default_random_tests() -> u32 {
+    100
+}
+
+fn default_generator_fuel() -> u64 {
+    10_000_000_000
 }
 
 #[derive(Deserialize)]
@@ -21,11 +83,69 @@ pub struct ProblemDir {
     pub mapping: HashMap<String, FileHash>,
 }
 
+pub const PROBLEM_CONFIG: &str = "config/problem.toml";
+
 impl ProblemDir {
     #[throws(anyhow::Error)]
-    pub fn new() -> Self {
-        let content = fs::read_to_string("config/problem.toml")?;
-        toml::from_str(&content)?
+    pub fn new(path: &Path) -> Self {
+        let content = fs::read_to_string(path)
+            .with_context(|| format!("could not read {}", path.display()))?;
+        let mut dir: Self = toml::from_str(&content)?;
+        // paths in the config are relative to the config file, not to the working directory
+        let base = path.parent().unwrap_or(Path::new(""));
+        for (hash, problem) in &mut dir.problems {
+            problem.hash = *hash;
+            problem.file_name.0 = base.join(&problem.file_name.0);
+            problem.statement = problem.statement.as_ref().map(|path| base.join(path));
+        }
+        dir
+    }
+
+    /// Checks that the problem files match their hash and that every name refers to a problem.
+    #[throws(anyhow::Error)]
+    pub fn check(&self) {
+        for (file_hash, problem) in &self.problems {
+            let real_file_hash = problem.file_name.hash()?;
+            if *file_hash != real_file_hash {
+                anyhow::bail!(
+                    "{} has hash {real_file_hash}, not {file_hash}",
+                    problem.file_name.0.display()
+                );
+            }
+            let wasm = problem.file_name.read(*file_hash)?;
+            verify_problem(&wasm).with_context(|| {
+                format!("{} is not a valid problem", problem.file_name.0.display())
+            })?;
+            problem.statement_html()?;
+        }
+        for (name, file_hash) in &self.mapping {
+            if !self.problems.contains_key(file_hash) {
+                anyhow::bail!("problem `{name}` refers to unknown problem {file_hash}");
+            }
+        }
+    }
+
+    /// Only the current version of each problem is benched, older versions are kept for their results.
+    pub fn is_current(&self, hash: FileHash) -> bool {
+        self.mapping.values().any(|current| *current == hash)
+    }
+}
+
+/// The current problem config, it can be replaced while the server is running.
+#[derive(Clone)]
+pub struct SharedProblemDir(Arc<RwLock<Arc<ProblemDir>>>);
+
+impl SharedProblemDir {
+    pub fn new(problem_dir: ProblemDir) -> Self {
+        Self(Arc::new(RwLock::new(Arc::new(problem_dir))))
+    }
+
+    pub fn get(&self) -> Arc<ProblemDir> {
+        self.0.read().unwrap().clone()
+    }
+
+    pub fn set(&self, problem_dir: ProblemDir) {
+        *self.0.write().unwrap() = Arc::new(problem_dir);
     }
 }
 
@@ -33,35 +153,26 @@ impl ProblemDir {
 pub struct ModulePath(pub std::path::PathBuf);
 
 impl ModulePath {
+    /// Reads the file and checks that it has the expected hash.
     #[throws(anyhow::Error)]
-    pub fn load(&self, engine: &Engine) -> Module {
-        if let Ok(module) = self.load_cached(engine) {
-            return module;
+    pub fn read(&self, hash: FileHash) -> Vec<u8> {
+        let buf = fs::read(&self.0)?;
+        if FileHash::new(&buf) != hash {
+            anyhow::bail!("{} does not have hash {hash}", self.0.display());
         }
-        let module = Module::from_file(engine, &self.0)?;
-        fs::write(self.cache_path()?, module.serialize()?)?;
-        module
+        buf
     }
 
     #[throws(anyhow::Error)]
-    fn load_cached(&self, engine: &Engine) -> Module {
-        let cache_path = self.cache_path()?;
-        unsafe { Module::deserialize_file(engine, cache_path)? }
-    }
-
-    #[throws(anyhow::Error)]
-    fn cache_path(&self) -> PathBuf {
-        let mut cache_path = self.0.clone();
-        let true = cache_path.set_extension("compiled") else {
-            anyhow::bail!("can not change extensions of path {}", cache_path.display())
-        };
-        cache_path
+    pub fn hash(&self) -> FileHash {
+        let buf = fs::read(&self.0)?;
+        FileHash::new(buf)
     }
 
     #[throws(anyhow::Error)]
-    pub fn hash(&self) -> FileHash {
+    pub fn full_hash(&self) -> ContentHash {
         let buf = fs::read(&self.0)?;
-        FileHash::new(buf)
+        ContentHash::new(buf)
     }
 
     #[throws(anyhow::Error)]
@@ -76,56 +187,167 @@ pub struct TaskInstance {
     pub answer: i64,
 }
 
+impl TaskInstance {
+    /// This is stored with the instance, to notice when the generator gives a different result.
+    pub fn hash(&self) -> ContentHash {
+        let mut data = self.input.to_vec();
+        data.extend(self.answer.to_le_bytes());
+        ContentHash::new(data)
+    }
+}
+
 impl Problem {
+    pub fn limits(&self) -> Limits {
+        Limits {
+            fuel: self.fuel_limit,
+            memory: self.memory_limit,
+            time: Duration::from_millis(self.time_limit_ms),
+        }
+    }
+
+    /// Renders the markdown statement, the file is read every time so it can be edited while the server is running.
+    #[throws(anyhow::Error)]
+    pub fn statement_html(&self) -> Option<String> {
+        let Some(path) = &self.statement else {
+            return None;
+        };
+        let markdown = fs::read_to_string(path)
+            .with_context(|| format!("could not read statement {}", path.display()))?;
+        let mut html = String::new();
+        pulldown_cmark::html::push_html(&mut html, pulldown_cmark::Parser::new(&markdown));
+        Some(html)
+    }
+
+    #[throws(anyhow::Error)]
+    pub fn generate(&self, modules: &ModuleCache, seed: i64) -> TaskInstance {
+        let module = modules.load(self.hash, || self.file_name.read(self.hash))?;
+        let mut store = Store::new(modules.engine(), ());
+        store.add_fuel(self.generator_fuel)?;
+        generate_instance(&mut store, &module, seed)?.0
+    }
+
+    /// Generates the instance twice, each time in a fresh store, returns `None` when the results differ.
     #[throws(anyhow::Error)]
-    pub fn generate(&self, engine: &Engine, seed: i64) -> TaskInstance {
-        let module = self.file_name.load(engine)?;
-        let mut store = Store::new(engine, ());
+    pub fn generate_checked(&self, modules: &ModuleCache, seed: i64) -> Option<TaskInstance> {
+        let instance = self.generate(modules, seed)?;
+        let again = self.generate(modules, seed)?;
+        (again.hash() == instance.hash()).then_some(instance)
+    }
+}
+
+/// Runs `generate` and then the reference `solution` of a problem module.
+///
+/// The fuel used by `solution` is returned when the engine consumes fuel.
+#[throws(anyhow::Error)]
+pub fn generate_instance<T>(
+    store: &mut Store<T>,
+    module: &Module,
+    seed: i64,
+) -> (TaskInstance, Option<u64>) {
+    // first instantiate, this calls optional start
+    let instance = Linker::new(store.engine()).instantiate(&mut *store, module)?;
+    // call the generator
+    let func: TypedFunc<_, (i32, i32)> = instance.get_typed_func(&mut *store, "generate")?;
+    let (offset, length) = func.call(&mut *store, seed)?;
+
+    // read the generated instance from wasm
+    let mut input = vec![0; length as usize].into_boxed_slice();
+    let memory = instance
+        .get_memory(&mut *store, "memory")
+        .context("memory was not defined")?;
+    memory.read(&*store, offset as usize, &mut input)?;
 
-        // first instantiate, this calls optional start
-        let instance = Linker::new(engine).instantiate(&mut store, &module)?;
-        // call the generator
-        let func: TypedFunc<_, (i32, i32)> = instance.get_typed_func(&mut store, "generate")?;
-        let (offset, length) = func.call(&mut store, seed)?;
+    let fuel_before = store.fuel_consumed();
+    let solution: TypedFunc<_, i64> = instance.get_typed_func(&mut *store, "solution")?;
+    let answer = solution.call(&mut *store, (offset, length))?;
+    let fuel = store
+        .fuel_consumed()
+        .zip(fuel_before)
+        .map(|(after, before)| after - before);
 
-        // read the generated instance from wasm
-        let mut input = vec![0; length as usize].into_boxed_slice();
-        let memory = instance
-            .get_memory(&mut store, "memory")
-            .context("memory was not defined")?;
-        memory.read(&store, offset as usize, &mut input)?;
+    (TaskInstance { input, answer }, fuel)
+}
 
-        let solution: TypedFunc<_, i64> = instance.get_typed_func(&mut store, "solution")?;
-        let answer = solution.call(&mut store, (offset, length))?;
+/// Checks the exports of a problem module, so that it fails when loading the config instead of while benching.
+#[throws(WasmError)]
+pub fn verify_problem(buf: &[u8]) {
+    let engine = Engine::default();
+    let module =
+        Module::from_binary(&engine, buf).map_err(|e| WasmError::Invalid(e.to_string()))?;
 
-        TaskInstance { input, answer }
+    let functions = [
+        (
+            "generate",
+            "a function with signature i64 -> (i32, i32)",
+            FuncType::new([ValType::I64], [ValType::I32, ValType::I32]),
+        ),
+        (
+            "solution",
+            "a function with signature (i32, i32) -> i64",
+            FuncType::new([ValType::I32, ValType::I32], [ValType::I64]),
+        ),
+    ];
+    for (name, expected, signature) in functions {
+        let ftype = module
+            .get_export(name)
+            .ok_or(WasmError::MissingExport(name))?;
+        if ftype.func() != Some(&signature) {
+            throw!(WasmError::WrongExport { name, expected });
+        }
     }
+    let mtype = module
+        .get_export("memory")
+        .ok_or(WasmError::MissingExport("memory"))?;
+    if mtype.memory().is_none() {
+        throw!(WasmError::WrongExport {
+            name: "memory",
+            expected: "a memory",
+        });
+    }
+
+    check_imports(&module)?;
 }
 
 #[cfg(test)]
 mod tests {
 
-    use wasmtime::{Config, Engine};
+    use std::{path::Path, time::Duration};
 
-    use crate::solution::Solution;
+    use crate::{
+        cache::{EngineKind, ModuleCache},
+        solution::{Limits, Solution},
+        store::BlobStore,
+    };
 
-    use super::ProblemDir;
+    use super::{ProblemDir, PROBLEM_CONFIG};
 
     #[test]
     fn gen_instance() -> anyhow::Result<()> {
-        let dir = ProblemDir::new()?;
-        let engine = Engine::default();
+        let dir = ProblemDir::new(Path::new(PROBLEM_CONFIG))?;
+        let modules = ModuleCache::new(EngineKind::Problem, BlobStore::new("solution"))?;
         let problem_hash = dir.mapping["parse"];
-        let problem = dir.problems[&problem_hash].generate(&engine, 30)?;
+        let problem = dir.problems[&problem_hash].generate(&modules, 30)?;
         assert_eq!(&*problem.input, b"30");
+        let again = dir.problems[&problem_hash].generate_checked(&modules, 30)?;
+        assert_eq!(again.map(|again| again.hash()), Some(problem.hash()));
 
         let solution = Solution {
             hash: "bDHNXb6S_4Y".parse().unwrap(),
         };
-        let engine = Engine::new(Config::new().consume_fuel(true))?;
-        let res = solution.run(&engine, &problem.input, 10000);
-        assert_eq!(res.answer, Some(30));
+        let modules = ModuleCache::new(EngineKind::Solution, BlobStore::new("solution"))?;
+        let limits = Limits {
+            fuel: 10000,
+            memory: 16 << 20,
+            time: Duration::from_secs(1),
+        };
+        let res = solution.run(&modules, &problem.input, &limits)?;
+        assert_eq!(res.answer.ok(), Some(30));
 
         Ok(())
     }
+
+    #[test]
+    fn problems_are_valid() -> anyhow::Result<()> {
+        ProblemDir::new(Path::new(PROBLEM_CONFIG))?.check()
+    }
 }
@@ -0,0 +1,161 @@
+use std::{fs, ops::Deref, thread, time::Duration, time::SystemTime};
+
+use rand::{thread_rng, RngCore};
+use rusqlite::Connection;
+use rust_query::{
+    client::QueryBuilder,
+    value::{UnixEpoch, Value},
+};
+
+use crate::{
+    cache::ModuleCache,
+    db,
+    problem::ProblemDir,
+    tables::{self, FileDummy, InstanceDummy, ProblemVersionDummy, SolutionDummy},
+    AppState,
+};
+
+const POLL_INTERVAL: Duration = Duration::from_secs(2);
+
+/// Adds the problem files and enough instances for every problem to the database.
+/// When a problem name refers to a new version, the solutions of the older versions are queued for it.
+/// New instances are generated twice, they are stored as invalid when the generator is not deterministic.
+///
+/// `lock` is called before every use of the connection, it is not held while generating instances.
+pub fn sync_problems<C: Deref<Target = Connection>>(
+    lock: impl Fn() -> C,
+    problem_dir: &ProblemDir,
+    generators: &ModuleCache,
+) -> anyhow::Result<()> {
+    let mut missing = vec![];
+    for (file_hash, problem) in &problem_dir.problems {
+        let full_hash = Some(problem.file_name.full_hash()?.to_string());
+        let file_size = problem.file_name.len()? as i64;
+
+        let conn = lock();
+        conn.new_query(|q| {
+            // on conflict do nothing
+            q.insert(FileDummy {
+                timestamp: q.select(UnixEpoch),
+                file_hash: q.select(i64::from(*file_hash)),
+                file_size: q.select(file_size),
+                full_hash: q.select(&full_hash),
+            })
+        });
+
+        // invalid instances are not benched, so they are replaced
+        let num = conn.new_query(|q| {
+            let count = q.query(|q| {
+                let instance = q.table(tables::Instance);
+                q.filter(instance.problem.file_hash.eq(i64::from(*file_hash)));
+                q.filter(instance.invalid.eq(0));
+                q.group().count_distinct(instance)
+            });
+            q.into_vec(1, |row| row.get(count))[0]
+        });
+        missing.push((file_hash, problem, num));
+    }
+
+    // add instances so that there are enough for the benchmark
+    let mut rng = thread_rng();
+    let mut instances = vec![];
+    for (file_hash, problem, num) in missing {
+        let mut needed = i64::from(problem.leaderboard_instances) - num;
+        // invalid seeds are replaced, but a generator that is never deterministic should not loop forever
+        let mut retries = needed;
+        while needed > 0 {
+            let seed = rng.next_u64() as i64;
+            match problem.generate_checked(generators, seed)? {
+                Some(instance) => {
+                    instances.push((file_hash, seed, Some(instance.hash().to_string())));
+                    needed -= 1;
+                }
+                None => {
+                    println!(
+                        "{} is not deterministic for seed {}",
+                        problem.file_name.0.display(),
+                        seed as u64
+                    );
+                    instances.push((file_hash, seed, None));
+                    if retries == 0 {
+                        break;
+                    }
+                    retries -= 1;
+                }
+            }
+        }
+    }
+
+    let conn = lock();
+    for (file_hash, seed, output_hash) in instances {
+        conn.new_query(|q| {
+            let problem = db::get_file(q, *file_hash);
+            q.insert(InstanceDummy {
+                problem: q.select(problem),
+                seed: q.select(seed),
+                timestamp: q.select(UnixEpoch),
+                // stored so the seed is not used again, invalid instances are not benched
+                output_hash: q.select(&output_hash),
+                invalid: q.select(i64::from(output_hash.is_none())),
+            })
+        });
+    }
+
+    for (name, file_hash) in &problem_dir.mapping {
+        conn.new_query(|q| {
+            let file = db::get_file(q, *file_hash);
+            q.insert(ProblemVersionDummy {
+                timestamp: q.select(UnixEpoch),
+                name: q.select(name.as_str()),
+                file: q.select(file),
+            })
+        });
+
+        // the original timestamp is kept, so the new instances are benched as backfill
+        conn.new_query(|q| {
+            let version = q.table(tables::ProblemVersion);
+            q.filter(version.name.eq(name.as_str()));
+            let solution = q.table(tables::Solution);
+            q.filter((&solution.problem).eq(&version.file));
+            let problem = db::get_file(q, *file_hash);
+            q.insert(SolutionDummy {
+                timestamp: q.select(&solution.timestamp),
+                program: q.select(&solution.program),
+                problem: q.select(problem),
+                random_tests: q.select(0),
+            })
+        });
+    }
+    Ok(())
+}
+
+/// Reloads the problem config when the file changes.
+pub fn watch_problem_dir(app: AppState) {
+    let path = &app.config.problem_config;
+    let modified = || fs::metadata(path).and_then(|m| m.modified()).ok();
+    let mut last_modified: Option<SystemTime> = modified();
+    loop {
+        thread::sleep(POLL_INTERVAL);
+        let current = modified();
+        if current == last_modified {
+            continue;
+        }
+        last_modified = current;
+
+        match reload(&app) {
+            Ok(()) => println!("reloaded {}", path.display()),
+            // the old config stays active
+            Err(e) => println!("could not reload {}: {e:#}", path.display()),
+        }
+    }
+}
+
+fn reload(app: &AppState) -> anyhow::Result<()> {
+    let problem_dir = ProblemDir::new(&app.config.problem_config)?;
+    problem_dir.check()?;
+    sync_problems(|| app.conn.lock(), &problem_dir, &app.generators)?;
+    app.problem_dir.set(problem_dir);
+    // there might be new instances to bench
+    app.conn.notify();
+    Ok(())
+}
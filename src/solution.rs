@@ -1,83 +1,324 @@
-use anyhow::Context;
-use fehler::throws;
+use std::{fmt::Display, time::Duration};
+
+use fehler::{throw, throws};
 use wasmtime::{
-    Engine, FuncType, GlobalType, Linker, Module, Mutability, Store, TypedFunc, ValType,
+    Engine, FuncType, GlobalType, Linker, Module, Mutability, ResourceLimiter, Store, Trap,
+    TypedFunc, ValType,
 };
 
-use crate::{hash::FileHash, problem::ModulePath};
+use crate::{cache::ModuleCache, hash::FileHash};
+
+/// The engine used to run solutions needs to have its epoch incremented with this interval.
+pub const EPOCH_TICK: Duration = Duration::from_millis(10);
 
 pub struct Solution {
     pub hash: FileHash,
 }
 
+#[derive(Debug, Clone, Copy)]
+pub struct Limits {
+    pub fuel: u64,
+    // maximum size of the linear memory in bytes
+    pub memory: usize,
+    // wall-clock time, only enforced when the engine has epoch interruption enabled
+    pub time: Duration,
+}
+
+impl Limits {
+    fn epoch_deadline(&self) -> u64 {
+        let ticks = self.time.as_millis() / EPOCH_TICK.as_millis();
+        (ticks as u64).max(1)
+    }
+}
+
 #[derive(Debug)]
 pub struct RunResult {
     pub fuel_used: u64,
-    pub answer: Option<i64>,
+    pub answer: Result<i64, Fault>,
+}
+
+/// Reasons for a solution to not return an answer.
+#[derive(Debug)]
+pub enum Fault {
+    OutOfFuel,
+    OutOfMemory,
+    Timeout,
+    Trap(String),
+}
+
+impl Fault {
+    /// This is the name stored in the `failure` table.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Fault::OutOfFuel => "out_of_fuel",
+            Fault::OutOfMemory => "out_of_memory",
+            Fault::Timeout => "timeout",
+            Fault::Trap(_) => "trap",
+        }
+    }
+
+    /// Extra information to help debug the failure.
+    pub fn message(&self) -> Option<String> {
+        match self {
+            Fault::Trap(msg) => Some(msg.clone()),
+            _ => None,
+        }
+    }
+}
+
+/// Errors while setting up the solution, before `solve` is called.
+#[derive(Debug)]
+pub enum RunError {
+    Load(anyhow::Error),
+    Fuel(anyhow::Error),
+    Instantiate(anyhow::Error),
+    HeapBase,
+    Memory,
+    Solve(anyhow::Error),
+    Grow(anyhow::Error),
+    Write(anyhow::Error),
+}
+
+impl RunError {
+    /// This is the name stored in the `failure` table.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            RunError::HeapBase | RunError::Memory | RunError::Solve(_) => "missing_export",
+            RunError::Grow(_) => "out_of_memory",
+            _ => "setup_error",
+        }
+    }
+
+    /// Extra information to help debug the failure.
+    pub fn message(&self) -> Option<String> {
+        match self {
+            RunError::HeapBase => Some("__heap_base".to_owned()),
+            RunError::Memory => Some("memory".to_owned()),
+            RunError::Solve(_) => Some("solve".to_owned()),
+            _ => Some(self.to_string()),
+        }
+    }
+}
+
+impl Display for RunError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RunError::Load(e) => write!(f, "could not load module: {e}"),
+            RunError::Fuel(e) => write!(f, "could not add fuel: {e}"),
+            RunError::Instantiate(e) => write!(f, "could not instantiate module: {e}"),
+            RunError::HeapBase => f.write_str("export `__heap_base` is not an i32 global"),
+            RunError::Memory => f.write_str("export `memory` is not a memory"),
+            RunError::Solve(e) => write!(f, "export `solve` can not be used: {e}"),
+            RunError::Grow(e) => write!(f, "could not grow memory for the input: {e}"),
+            RunError::Write(e) => write!(f, "could not write the input to memory: {e}"),
+        }
+    }
+}
+
+impl std::error::Error for RunError {}
+
+struct MemoryLimiter {
+    limit: usize,
+    // set when the solution tried to grow beyond the limit
+    exceeded: bool,
+}
+
+impl ResourceLimiter for MemoryLimiter {
+    fn memory_growing(
+        &mut self,
+        _current: usize,
+        desired: usize,
+        _maximum: Option<usize>,
+    ) -> anyhow::Result<bool> {
+        let allowed = desired <= self.limit;
+        self.exceeded |= !allowed;
+        Ok(allowed)
+    }
+
+    fn table_growing(
+        &mut self,
+        _current: u32,
+        _desired: u32,
+        _maximum: Option<u32>,
+    ) -> anyhow::Result<bool> {
+        Ok(true)
+    }
 }
 
 impl Solution {
-    pub fn run(&self, engine: &Engine, data: &[u8], fuel: u64) -> RunResult {
-        let path = format!("solution/{}.wasm", &self.hash);
-        let module = ModulePath(path.into()).load(engine).unwrap();
+    pub fn run(
+        &self,
+        modules: &ModuleCache,
+        data: &[u8],
+        limits: &Limits,
+    ) -> Result<RunResult, RunError> {
+        let module = modules
+            .load(self.hash, || modules.store().read(self.hash))
+            .map_err(RunError::Load)?;
+        let engine = modules.engine();
         // first instantiate, this calls optional start
         // add some fuel here so the program can run
-        let mut store = Store::new(engine, ());
-        store.add_fuel(fuel).unwrap();
-        let instance = Linker::new(engine)
-            .instantiate(&mut store, &module)
-            .unwrap();
+        let limiter = MemoryLimiter {
+            limit: limits.memory,
+            exceeded: false,
+        };
+        let mut store = Store::new(engine, limiter);
+        store.limiter(|limiter| limiter);
+        store.add_fuel(limits.fuel).map_err(RunError::Fuel)?;
+        store.set_epoch_deadline(limits.epoch_deadline());
+        let instance = Linker::new(engine).instantiate(&mut store, &module);
+        // the initial memory of the module is larger than the limit
+        if store.data().exceeded {
+            return Ok(RunResult {
+                fuel_used: 0,
+                answer: Err(Fault::OutOfMemory),
+            });
+        }
+        let instance = instance.map_err(RunError::Instantiate)?;
 
         // we need to get the base of the wasm heap so we don't interfere with stack space.
-        let heap_base = instance.get_global(&mut store, "__heap_base").unwrap();
-        let heap_base = heap_base.get(&mut store).i32().unwrap();
+        let heap_base = instance
+            .get_global(&mut store, "__heap_base")
+            .and_then(|g| g.get(&mut store).i32())
+            .ok_or(RunError::HeapBase)?;
 
         // now we can write the actual input
-        let memory = instance.get_memory(&mut store, "memory").unwrap();
+        let memory = instance
+            .get_memory(&mut store, "memory")
+            .ok_or(RunError::Memory)?;
 
-        let func: TypedFunc<i32, i64> = instance.get_typed_func(&mut store, "solve").unwrap();
+        let func: TypedFunc<i32, i64> = instance
+            .get_typed_func(&mut store, "solve")
+            .map_err(RunError::Solve)?;
 
-        memory.grow(&mut store, 1).unwrap();
-        memory.write(&mut store, heap_base as usize, data).unwrap();
+        memory.grow(&mut store, 1).map_err(RunError::Grow)?;
+        memory
+            .write(&mut store, heap_base as usize, data)
+            .map_err(|e| RunError::Write(e.into()))?;
 
         // call the actual solve function
         let answer = func
             .call(&mut store, data.len() as i32)
-            .inspect_err(|e| println!("ERROR: {} {e}", self.hash))
-            .ok();
+            .map_err(|e| fault(&store, e));
 
-        RunResult {
-            fuel_used: store.fuel_consumed().unwrap(),
+        Ok(RunResult {
+            fuel_used: store.fuel_consumed().unwrap_or_default(),
             answer,
+        })
+    }
+}
+
+fn fault(store: &Store<MemoryLimiter>, error: anyhow::Error) -> Fault {
+    match error.downcast_ref::<Trap>() {
+        Some(Trap::OutOfFuel) => Fault::OutOfFuel,
+        Some(Trap::Interrupt) => Fault::Timeout,
+        // failing to grow memory often results in a trap later on
+        _ if store.data().exceeded => Fault::OutOfMemory,
+        _ => Fault::Trap(error.to_string()),
+    }
+}
+
+/// Why a wasm file can not be used as a solution or problem.
+#[derive(Debug)]
+pub enum WasmError {
+    Invalid(String),
+    MissingExport(&'static str),
+    WrongExport {
+        name: &'static str,
+        expected: &'static str,
+    },
+    Imports(Vec<String>),
+}
+
+impl WasmError {
+    /// Explains how to fix the problem.
+    pub fn help(&self) -> &'static str {
+        match self {
+            WasmError::Invalid(_) => "Make sure to upload a compiled `.wasm` file, not the text format or source code.",
+            WasmError::MissingExport("solve") | WasmError::WrongExport { name: "solve", .. } => {
+                "The solution needs to export `solve`, which takes the length of the input and returns the answer."
+            }
+            WasmError::MissingExport("generate") | WasmError::WrongExport { name: "generate", .. } => {
+                "The problem needs to export `generate`, which takes a seed and returns the offset and length of the input."
+            }
+            WasmError::MissingExport("solution") | WasmError::WrongExport { name: "solution", .. } => {
+                "The problem needs to export `solution`, which takes the offset and length of the input and returns the answer."
+            }
+            WasmError::MissingExport("memory") | WasmError::WrongExport { name: "memory", .. } => {
+                "The input is passed through the exported memory."
+            }
+            WasmError::MissingExport(_) | WasmError::WrongExport { .. } => {
+                "The input is written to memory starting at `__heap_base`, most toolchains export it when linking with `--export=__heap_base`."
+            }
+            WasmError::Imports(_) => {
+                "Modules can not use host functions, make sure to compile without a standard library or WASI."
+            }
         }
     }
 }
 
-#[throws(anyhow::Error)]
+impl Display for WasmError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            WasmError::Invalid(e) => write!(f, "not a valid wasm module: {e}"),
+            WasmError::MissingExport(name) => write!(f, "expect export `{name}`"),
+            WasmError::WrongExport { name, expected } => {
+                write!(f, "export `{name}` is not {expected}")
+            }
+            WasmError::Imports(imports) => {
+                write!(f, "expected no imports, found {}", imports.join(", "))
+            }
+        }
+    }
+}
+
+impl std::error::Error for WasmError {}
+
+#[throws(WasmError)]
 pub fn verify_wasm(buf: &[u8]) {
     let engine = Engine::default();
-    let module = Module::from_binary(&engine, buf)?;
+    let module =
+        Module::from_binary(&engine, buf).map_err(|e| WasmError::Invalid(e.to_string()))?;
 
     let ftype = module
         .get_export("solve")
-        .context("expect export `solve`")?;
+        .ok_or(WasmError::MissingExport("solve"))?;
     if ftype.func() != Some(&FuncType::new([ValType::I32], [ValType::I64])) {
-        anyhow::bail!("export `solve` does not have signature i32 -> i64");
+        throw!(WasmError::WrongExport {
+            name: "solve",
+            expected: "a function with signature i32 -> i64",
+        });
     }
     let mtype = module
         .get_export("memory")
-        .context("expect export `memory`")?;
+        .ok_or(WasmError::MissingExport("memory"))?;
     if mtype.memory().is_none() {
-        anyhow::bail!("export `memory` is not a memory");
+        throw!(WasmError::WrongExport {
+            name: "memory",
+            expected: "a memory",
+        });
     }
     let btype = module
         .get_export("__heap_base")
-        .context("expect export `__heap_base`")?;
+        .ok_or(WasmError::MissingExport("__heap_base"))?;
     if btype.global() != Some(&GlobalType::new(ValType::I32, Mutability::Const)) {
-        anyhow::bail!("export `__heap_base` is not a global const i32");
+        throw!(WasmError::WrongExport {
+            name: "__heap_base",
+            expected: "a global const i32",
+        });
     }
 
-    if module.imports().len() != 0 {
-        anyhow::bail!("expected no imports");
+    check_imports(&module)?;
+}
+
+// modules are instantiated without any host functions
+#[throws(WasmError)]
+pub(crate) fn check_imports(module: &Module) {
+    let imports: Vec<_> = module
+        .imports()
+        .map(|import| format!("`{}::{}`", import.module(), import.name()))
+        .collect();
+    if !imports.is_empty() {
+        throw!(WasmError::Imports(imports));
     }
 }
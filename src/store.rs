@@ -0,0 +1,163 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use fehler::throws;
+use rand::{thread_rng, RngCore};
+use wasmtime::{Engine, Module};
+
+use crate::hash::{ContentHash, FileHash};
+
+/// Content addressed storage for uploaded wasm files and precompiled modules.
+///
+/// Files are stored as `<root>/<first two characters of hash>/<hash>.<extension>`.
+#[derive(Debug, Clone)]
+pub struct BlobStore {
+    root: PathBuf,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BlobState {
+    Ok,
+    Missing,
+    // the content does not match the hash
+    Corrupted,
+}
+
+impl BlobStore {
+    pub fn new(root: impl Into<PathBuf>) -> Self {
+        Self { root: root.into() }
+    }
+
+    fn path(&self, hash: FileHash, extension: &str) -> PathBuf {
+        let name = hash.to_string();
+        self.root
+            .join(&name[..2])
+            .join(format!("{name}.{extension}"))
+    }
+
+    // files from before sharding are moved into their shard on first use
+    #[throws(anyhow::Error)]
+    fn wasm_path(&self, hash: FileHash) -> PathBuf {
+        let path = self.path(hash, "wasm");
+        let legacy = self.root.join(format!("{hash}.wasm"));
+        if !path.exists() && legacy.exists() {
+            fs::create_dir_all(path.parent().unwrap())?;
+            fs::rename(legacy, &path)?;
+        }
+        path
+    }
+
+    /// Stores the file and returns its hash, this does nothing if the file is already stored.
+    /// Fails if a different file with the same hash is stored.
+    #[throws(anyhow::Error)]
+    pub fn write(&self, data: &[u8]) -> FileHash {
+        let hash = FileHash::new(data);
+        let path = self.wasm_path(hash)?;
+        match fs::read(&path) {
+            Ok(existing) if existing == data => {}
+            Ok(existing) if FileHash::new(&existing) == hash => {
+                anyhow::bail!("hash collision with {}", path.display())
+            }
+            // the file is missing or corrupted
+            _ => write_atomic(&path, data)?,
+        }
+        hash
+    }
+
+    /// Reads the file and checks that it still matches its hash.
+    #[throws(anyhow::Error)]
+    pub fn read(&self, hash: FileHash) -> Vec<u8> {
+        let path = self.wasm_path(hash)?;
+        let data = fs::read(&path)
+            .map_err(|e| anyhow::anyhow!("could not read {}: {e}", path.display()))?;
+        if FileHash::new(&data) != hash {
+            anyhow::bail!("file {} is corrupted", path.display());
+        }
+        data
+    }
+
+    pub fn check(&self, hash: FileHash) -> BlobState {
+        let Ok(path) = self.wasm_path(hash) else {
+            return BlobState::Missing;
+        };
+        match fs::read(path) {
+            Err(_) => BlobState::Missing,
+            Ok(data) if FileHash::new(data) != hash => BlobState::Corrupted,
+            Ok(_) => BlobState::Ok,
+        }
+    }
+
+    /// Loads the precompiled module or compiles `wasm` when there is none.
+    /// The `tag` identifies the engine config, it is part of the precompiled file name.
+    ///
+    /// Precompiled files are only used when they match the hash that is stored next to them,
+    /// wasmtime does not check them and loading a corrupted file is undefined behaviour.
+    #[throws(anyhow::Error)]
+    pub fn load_module(
+        &self,
+        engine: &Engine,
+        tag: &str,
+        hash: FileHash,
+        wasm: impl FnOnce() -> anyhow::Result<Vec<u8>>,
+    ) -> Module {
+        let compiled = self.path(hash, &format!("{tag}.compiled"));
+        let compiled_hash = self.path(hash, &format!("{tag}.compiled.hash"));
+        if let (Ok(data), Ok(expected)) = (fs::read(&compiled), fs::read_to_string(&compiled_hash))
+        {
+            if ContentHash::new(&data).to_string() == expected {
+                // wasmtime also refuses files from a different version or config, but then we recompile
+                if let Ok(module) = unsafe { Module::deserialize(engine, &data) } {
+                    return module;
+                }
+            }
+        }
+        let module = Module::from_binary(engine, &wasm()?)?;
+        let data = module.serialize()?;
+        write_atomic(&compiled, &data)?;
+        // when the server stops between these writes, the old hash does not match and we recompile
+        write_atomic(
+            &compiled_hash,
+            ContentHash::new(&data).to_string().as_bytes(),
+        )?;
+        module
+    }
+}
+
+// readers never see a partially written file, because rename is atomic
+#[throws(anyhow::Error)]
+fn write_atomic(path: &Path, data: &[u8]) {
+    fs::create_dir_all(path.parent().unwrap())?;
+    let mut tmp = path.as_os_str().to_owned();
+    tmp.push(format!(".tmp-{:x}", thread_rng().next_u64()));
+    fs::write(&tmp, data)?;
+    fs::rename(&tmp, path)?;
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::{BlobState, BlobStore};
+    use crate::hash::FileHash;
+
+    #[test]
+    fn detect_corruption() -> anyhow::Result<()> {
+        let root = std::env::temp_dir().join(format!("blob-store-{}", std::process::id()));
+        let store = BlobStore::new(&root);
+
+        let hash = store.write(b"hello")?;
+        assert_eq!(hash, FileHash::new(b"hello"));
+        assert_eq!(store.read(hash)?, b"hello");
+
+        fs::write(store.path(hash, "wasm"), b"world")?;
+        assert_eq!(store.check(hash), BlobState::Corrupted);
+        assert!(store.read(hash).is_err());
+
+        assert_eq!(store.check(FileHash::new(b"other")), BlobState::Missing);
+
+        fs::remove_dir_all(root)?;
+        Ok(())
+    }
+}